@@ -0,0 +1,173 @@
+//! An example contract which only accepts grant applications from accounts with a valid
+//! kycDAO token, by calling `kyc_check` on the kycDAO NTNFT contract.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::UnorderedSet;
+use near_sdk::*;
+
+use serde::{Serialize, Deserialize};
+
+const GAS_FOR_KYC_CHECK: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_ON_KYC_CHECK: Gas = Gas(10_000_000_000_000);
+
+/// Result of `kyc_check` on the kycDAO NTNFT contract
+#[derive(Serialize, Deserialize, Debug)]
+pub struct KycCheckResult {
+    pub valid: bool,
+    pub tier: Option<String>,
+    pub expiry: Option<u64>,
+    pub token_id: Option<String>,
+}
+
+#[ext_contract(ext_kyc_validity)]
+pub trait KycValidity {
+    fn kyc_check(&self, account_id: AccountId, min_tier: Option<String>) -> KycCheckResult;
+}
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct GrantProgram {
+    kyc_validity: AccountId,
+    /// Tier required from applicants, any tier is accepted if not set
+    min_tier: Option<String>,
+    grantees: UnorderedSet<AccountId>,
+}
+
+#[near_bindgen]
+impl GrantProgram {
+    #[init]
+    pub fn new(kyc_validity: AccountId, min_tier: Option<String>) -> Self {
+        assert!(!env::state_exists(), "Already initialized");
+        Self {
+            kyc_validity,
+            min_tier,
+            grantees: UnorderedSet::new(b"g".to_vec()),
+        }
+    }
+
+    /// Checks the KYC of the predecessor, then accepts the grant application in `on_kyc_check`
+    pub fn accept_new_grant(&mut self) -> Promise {
+        let applicant = env::predecessor_account_id();
+        ext_kyc_validity::ext(self.kyc_validity.clone())
+            .with_static_gas(GAS_FOR_KYC_CHECK)
+            .kyc_check(applicant.clone(), self.min_tier.clone())
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_ON_KYC_CHECK)
+                    .on_kyc_check(applicant)
+            )
+    }
+
+    #[private]
+    pub fn on_kyc_check(&mut self, applicant: AccountId, #[callback_result] result: Result<KycCheckResult, PromiseError>) {
+        let result = result.expect("KYC check failed");
+        assert!(result.valid, "You must have a valid KYC token to use this contract");
+
+        // proceed with accepting new grant application from the applicant
+        self.grantees.insert(&applicant);
+        log!("Accepted grant application from {}", applicant);
+    }
+
+    pub fn is_grantee(&self, account_id: AccountId) -> bool {
+        self.grantees.contains(&account_id)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::{serde_json, testing_env};
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+
+    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(accounts(0))
+            .predecessor_account_id(predecessor_account_id);
+        builder
+    }
+
+    /// Converts a `kyc_check` result of the kycDAO NTNFT contract the way it is passed to the callback
+    fn from_contract_result(result: kycdao_ntnft::KycCheckResult) -> KycCheckResult {
+        let json = serde_json::to_string(&result).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    /// Response of `kyc_check` as serialized by the kycDAO NTNFT contract
+    fn kyc_check_response(valid: bool) -> Result<KycCheckResult, PromiseError> {
+        Ok(from_contract_result(kycdao_ntnft::KycCheckResult {
+            valid,
+            tier: Some("KYC_1".to_string()),
+            expiry: Some(1700000000),
+            token_id: Some("0".to_string()),
+        }))
+    }
+
+    #[test]
+    fn test_kyc_check_result_format() {
+        let result = from_contract_result(kycdao_ntnft::KycCheckResult {
+            valid: true,
+            tier: Some("KYC_2".to_string()),
+            expiry: Some(1700000000),
+            token_id: Some("3".to_string()),
+        });
+        assert!(result.valid);
+        assert_eq!(result.tier, Some("KYC_2".to_string()));
+        assert_eq!(result.expiry, Some(1700000000));
+        assert_eq!(result.token_id, Some("3".to_string()));
+
+        // Accounts without a token
+        let result = from_contract_result(kycdao_ntnft::KycCheckResult {
+            valid: false,
+            tier: None,
+            expiry: None,
+            token_id: None,
+        });
+        assert!(!result.valid);
+        assert!(result.tier.is_none() && result.expiry.is_none() && result.token_id.is_none());
+    }
+
+    #[test]
+    fn test_accept_new_grant() {
+        let mut context = get_context(accounts(2));
+        testing_env!(context.build());
+        let mut contract = GrantProgram::new(accounts(1), Some("KYC_1".to_string()));
+
+        contract.accept_new_grant();
+        let receipts = get_created_receipts();
+        assert_eq!(receipts[0].receiver_id, accounts(1));
+        match &receipts[0].actions[0] {
+            VmAction::FunctionCall { function_name, args, .. } => {
+                assert_eq!(function_name, "kyc_check");
+                assert_eq!(args, br#"{"account_id":"charlie","min_tier":"KYC_1"}"#);
+            },
+            _ => panic!("Expected a function call"),
+        }
+        assert_eq!(receipts[1].receiver_id, accounts(0));
+
+        // The callback is called by the contract itself with the result of `kyc_check`
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.on_kyc_check(accounts(2), kyc_check_response(true));
+        assert!(contract.is_grantee(accounts(2)));
+    }
+
+    #[test]
+    #[should_panic(expected = "You must have a valid KYC token to use this contract")]
+    fn test_reject_without_valid_token() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = GrantProgram::new(accounts(1), None);
+
+        contract.on_kyc_check(accounts(2), kyc_check_response(false));
+    }
+
+    #[test]
+    #[should_panic(expected = "KYC check failed")]
+    fn test_reject_on_failed_kyc_check() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = GrantProgram::new(accounts(1), None);
+
+        contract.on_kyc_check(accounts(2), Err(PromiseError::Failed));
+    }
+}
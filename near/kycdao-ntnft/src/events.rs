@@ -0,0 +1,434 @@
+//! NEP-297 events emitted by the contract
+//!
+//! Mints and burns are logged with the nep171 `nft_mint` and `nft_burn` events, everything else specific to
+//! kycDAO (authorizations, status changes, prices) is logged under the `kycdao` standard.
+//!
+//! <https://github.com/near/NEPs/blob/master/neps/nep-0297.md>
+
+use near_sdk::json_types::U128;
+use near_sdk::{env, AccountId, PublicKey};
+use serde::Serialize;
+
+use crate::roles::Role;
+use crate::{DurationPrice, PausableFeature, RevocationReason, TokenPolicy, TokenUriTemplate};
+
+pub const NEP171_STANDARD_VERSION: &str = "1.0.0";
+pub const KYCDAO_STANDARD_VERSION: &str = "1.0.0";
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "standard")]
+#[serde(rename_all = "snake_case")]
+enum NearEvent<'a> {
+    Nep171(Nep171Event<'a>),
+    Kycdao(KycdaoEvent<'a>),
+}
+
+impl NearEvent<'_> {
+    fn emit(self) {
+        // Events cannot fail to serialize so fine to panic on error
+        let json = serde_json::to_string(&self).unwrap_or_else(|_| env::abort());
+        env::log_str(&format!("EVENT_JSON:{}", json));
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Nep171Event<'a> {
+    version: &'static str,
+    #[serde(flatten)]
+    event_kind: Nep171EventKind<'a>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+enum Nep171EventKind<'a> {
+    NftMint(&'a [NftMint<'a>]),
+    NftBurn(&'a [NftBurn<'a>]),
+}
+
+#[derive(Serialize, Debug)]
+struct KycdaoEvent<'a> {
+    version: &'static str,
+    #[serde(flatten)]
+    event_kind: KycdaoEventKind<'a>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+enum KycdaoEventKind<'a> {
+    MintAuthorized(&'a [MintAuthorized<'a>]),
+    MintAuthorizationCancelled(&'a [MintAuthorizationCancelled<'a>]),
+    TokenRevoked(&'a [TokenRevoked<'a>]),
+    TokenReinstated(&'a [TokenReinstated<'a>]),
+    ExpiryUpdated(&'a [ExpiryUpdated<'a>]),
+    TierChanged(&'a [TierChanged<'a>]),
+    PriceUpdated(&'a [PriceUpdated]),
+    StorageVersionUpdated(&'a [StorageVersionUpdated<'a>]),
+    RoleGranted(&'a [RoleGranted<'a>]),
+    RoleRevoked(&'a [RoleRevoked<'a>]),
+    TierUpdated(&'a [TierUpdated<'a>]),
+    TierRemoved(&'a [TierRemoved<'a>]),
+    TierPriceUpdated(&'a [TierPriceUpdated<'a>]),
+    SubscriptionRenewed(&'a [SubscriptionRenewed<'a>]),
+    BalanceSent(&'a [BalanceSent<'a>]),
+    TokenUriUpdated(&'a [TokenUriUpdated<'a>]),
+    TokenPolicyUpdated(&'a [TokenPolicyUpdated]),
+    TokenUpdateAuthorized(&'a [TokenUpdateAuthorized<'a>]),
+    TokenUpdated(&'a [TokenUpdated<'a>]),
+    Paused(&'a [Paused<'a>]),
+    Unpaused(&'a [Unpaused<'a>]),
+    SignerKeyUpdated(&'a [SignerKeyUpdated<'a>]),
+    SafeAddressUpdated(&'a [SafeAddressUpdated<'a>]),
+    BalanceReserveUpdated(&'a [BalanceReserveUpdated<'a>]),
+    PriceFeedUpdated(&'a [PriceFeedUpdated<'a>]),
+    PriceMaxAgeUpdated(&'a [PriceMaxAgeUpdated<'a>]),
+    LegacyMintAuthorizationPurged(&'a [LegacyMintAuthorizationPurged]),
+}
+
+fn emit_nep171(event_kind: Nep171EventKind) {
+    NearEvent::Nep171(Nep171Event { version: NEP171_STANDARD_VERSION, event_kind }).emit()
+}
+
+fn emit_kycdao(event_kind: KycdaoEventKind) {
+    NearEvent::Kycdao(KycdaoEvent { version: KYCDAO_STANDARD_VERSION, event_kind }).emit()
+}
+
+/// Logging of the events below, `emit_many` logs several events of the same kind in one log entry
+pub trait Event: Sized {
+    fn emit_many(data: &[Self]);
+
+    fn emit(self) {
+        Self::emit_many(&[self])
+    }
+}
+
+/// Implements `Event` for events of a standard, each has a variant with the same name in the event kind enum
+macro_rules! impl_events {
+    ($emit:ident, $kind:ident, $($event:ident $(<$lt:lifetime>)?),* $(,)?) => {
+        $(
+            impl$(<$lt>)? Event for $event$(<$lt>)? {
+                fn emit_many(data: &[Self]) {
+                    $emit($kind::$event(data))
+                }
+            }
+        )*
+    };
+}
+
+impl_events!(
+    emit_nep171,
+    Nep171EventKind,
+    NftMint<'a>,
+    NftBurn<'a>,
+);
+
+impl_events!(
+    emit_kycdao,
+    KycdaoEventKind,
+    MintAuthorized<'a>,
+    MintAuthorizationCancelled<'a>,
+    TokenRevoked<'a>,
+    TokenReinstated<'a>,
+    ExpiryUpdated<'a>,
+    TierChanged<'a>,
+    PriceUpdated,
+    StorageVersionUpdated<'a>,
+    RoleGranted<'a>,
+    RoleRevoked<'a>,
+    TierUpdated<'a>,
+    TierRemoved<'a>,
+    TierPriceUpdated<'a>,
+    SubscriptionRenewed<'a>,
+    BalanceSent<'a>,
+    TokenUriUpdated<'a>,
+    TokenPolicyUpdated,
+    TokenUpdateAuthorized<'a>,
+    TokenUpdated<'a>,
+    Paused<'a>,
+    Unpaused<'a>,
+    SignerKeyUpdated<'a>,
+    SafeAddressUpdated<'a>,
+    BalanceReserveUpdated<'a>,
+    PriceFeedUpdated<'a>,
+    PriceMaxAgeUpdated<'a>,
+    LegacyMintAuthorizationPurged,
+);
+
+/// A token was minted
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct NftMint<'a> {
+    pub owner_id: &'a AccountId,
+    pub token_ids: &'a [&'a str],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+/// A token was burned
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct NftBurn<'a> {
+    pub owner_id: &'a AccountId,
+    pub token_ids: &'a [&'a str],
+    /// The account which burned the token, if not the owner
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authorized_id: Option<&'a AccountId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<&'a str>,
+}
+
+/// A mint was authorized for an account by the mint authorizer
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct MintAuthorized<'a> {
+    pub auth_code: u32,
+    pub dst: &'a AccountId,
+    pub tier: &'a str,
+    pub expiry: Option<u64>,
+    pub seconds_to_pay: u32,
+    /// The authorization cannot be used after this (epoch time in seconds)
+    pub valid_until: Option<u64>,
+}
+
+/// A pending mint authorization was cancelled by the mint authorizer, or purged after its deadline
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct MintAuthorizationCancelled<'a> {
+    pub auth_code: u32,
+    pub dst: &'a AccountId,
+}
+
+/// A token was marked as not verified
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct TokenRevoked<'a> {
+    pub token_id: &'a str,
+    pub owner_id: &'a AccountId,
+    pub reason: RevocationReason,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<&'a str>,
+    /// The account which changed the status
+    pub actor: &'a AccountId,
+}
+
+/// A token was marked as verified again
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct TokenReinstated<'a> {
+    pub token_id: &'a str,
+    pub owner_id: &'a AccountId,
+    pub reason: RevocationReason,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<&'a str>,
+    /// The account which changed the status
+    pub actor: &'a AccountId,
+}
+
+/// The expiry of a token changed (epoch time in seconds)
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct ExpiryUpdated<'a> {
+    pub token_id: &'a str,
+    pub old_expiry: Option<u64>,
+    pub new_expiry: Option<u64>,
+}
+
+/// The verification tier of a token changed
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct TierChanged<'a> {
+    pub token_id: &'a str,
+    pub old_tier: &'a str,
+    pub new_tier: &'a str,
+}
+
+/// The subscription cost per year changed, in USD with SUBSCRIPTION_COST_DECIMALS decimals
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct PriceUpdated {
+    pub subscription_cost_per_year: u32,
+}
+
+/// The contract storage was migrated to a new version
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct StorageVersionUpdated<'a> {
+    pub version: &'a str,
+}
+
+/// A role was granted to an account
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct RoleGranted<'a> {
+    pub role: Role,
+    pub account_id: &'a AccountId,
+    /// The account which granted the role
+    pub sender: &'a AccountId,
+}
+
+/// A role was revoked from an account
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct RoleRevoked<'a> {
+    pub role: Role,
+    pub account_id: &'a AccountId,
+    /// The account which revoked the role
+    pub sender: &'a AccountId,
+}
+
+/// A tier was added to or updated in the tier registry
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct TierUpdated<'a> {
+    pub tier: &'a str,
+    pub rank: u32,
+    pub display_name: &'a str,
+    pub subscription_cost_per_year: Option<u32>,
+    pub requires_reverification: bool,
+}
+
+/// A tier was removed from the tier registry
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct TierRemoved<'a> {
+    pub tier: &'a str,
+}
+
+/// The subscription costs of a tier changed
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct TierPriceUpdated<'a> {
+    pub tier: &'a str,
+    pub subscription_cost_per_year: Option<u32>,
+    pub duration_prices: &'a [DurationPrice],
+}
+
+/// The subscription of a token was paid for and its expiry extended (epoch time in seconds)
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct SubscriptionRenewed<'a> {
+    pub token_id: &'a str,
+    pub owner_id: &'a AccountId,
+    pub seconds: u32,
+    pub old_expiry: Option<u64>,
+    pub new_expiry: u64,
+    /// The amount paid in yoctoNEAR
+    pub cost: U128,
+}
+
+/// Payments collected by the contract were sent out
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct BalanceSent<'a> {
+    pub recipient: &'a AccountId,
+    /// The amount sent in yoctoNEAR
+    pub amount: U128,
+}
+
+/// The base URI or the token URI template changed
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct TokenUriUpdated<'a> {
+    pub base_uri: Option<&'a str>,
+    pub template: TokenUriTemplate,
+}
+
+/// The token policy changed
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct TokenPolicyUpdated {
+    pub policy: TokenPolicy,
+}
+
+/// A re-verification of a token was authorized with `authorize_token_update`
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct TokenUpdateAuthorized<'a> {
+    pub token_id: &'a str,
+    pub auth_code: u32,
+    pub tier: &'a str,
+    pub expiry: Option<u64>,
+    pub seconds_to_pay: u32,
+}
+
+/// The owner of a token applied its pending update
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct TokenUpdated<'a> {
+    pub token_id: &'a str,
+    pub owner_id: &'a AccountId,
+    pub tier: &'a str,
+    pub expiry: Option<u64>,
+    /// The amount paid in yoctoNEAR, excluding storage
+    pub cost: U128,
+}
+
+/// A part of the contract was paused
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct Paused<'a> {
+    pub feature: PausableFeature,
+    /// The owner or guardian which paused it
+    pub actor: &'a AccountId,
+}
+
+/// A part of the contract was unpaused by the owner
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct Unpaused<'a> {
+    pub feature: PausableFeature,
+    pub actor: &'a AccountId,
+}
+
+/// The public key verifying the signatures of a minter was set, or removed if None
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct SignerKeyUpdated<'a> {
+    pub account_id: &'a AccountId,
+    pub public_key: Option<&'a PublicKey>,
+    pub sender: &'a AccountId,
+}
+
+/// The treasury account which `send_balance_to_safe` sends to changed
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct SafeAddressUpdated<'a> {
+    pub address: &'a AccountId,
+    pub sender: &'a AccountId,
+}
+
+/// The balance kept in the contract when sending the balance out changed
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct BalanceReserveUpdated<'a> {
+    /// The reserve in yoctoNEAR
+    pub reserve: U128,
+    pub sender: &'a AccountId,
+}
+
+/// The price feed used for native - USD conversions changed
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct PriceFeedUpdated<'a> {
+    pub address: &'a AccountId,
+    pub asset_id: &'a str,
+    pub sender: &'a AccountId,
+}
+
+/// The maximum age of a price feed price changed
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct PriceMaxAgeUpdated<'a> {
+    pub max_age_secs: u32,
+    pub sender: &'a AccountId,
+}
+
+/// An expired mint authorization made before v0.5.0 was purged. Its code and account were not stored
+/// and cannot be recovered from its digest, so only the digest is logged
+#[must_use = "don't forget to `.emit()` this event"]
+#[derive(Serialize, Debug)]
+pub struct LegacyMintAuthorizationPurged {
+    /// The legacy digest of the authorization, hex encoded
+    pub digest: String,
+}
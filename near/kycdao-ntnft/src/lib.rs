@@ -1,7 +1,10 @@
+pub mod events;
+mod migrations;
 mod price_feed;
+pub mod roles;
 
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LookupMap, LazyOption, UnorderedMap};
+use near_sdk::collections::{LookupMap, LookupSet, LazyOption, UnorderedMap, UnorderedSet};
 use near_sdk::*;
 use near_sdk::env::{keccak256, block_timestamp};
 use near_contract_standards::upgrade::Ownable;
@@ -9,16 +12,51 @@ use near_contract_standards::ntnft::{Token, NTNFT, TokenId};
 use near_contract_standards::ntnft::metadata::*;
 
 use serde::{Serialize, Deserialize};
-use crate::price_feed::{PriceFeed, PriceFeedMocked};
+use crate::price_feed::{PriceFeed, PriceData};
+use crate::events::*;
+use crate::roles::{Role, Roles};
 use near_sdk::json_types::U128;
-use std::str::FromStr;
+use ed25519_dalek::Verifier;
 
 type MintAuthorizationCode = u32;
 
+/// Version of the contract, also used as the version of the stored state.
+/// Bump it with every release changing the `KycdaoNTNFT` layout, see the Migrations section of the README
+pub const VERSION: &str = "0.5.0";
 pub const SUBSCRIPTION_COST_DECIMALS: u8 = 8;
 const YOCTONEAR_TO_NATIVE_DECIMALS: u8 = 24;
 const SECS_IN_YEAR: u128 = 365 * 24 * 60 * 60;
 const DEFAULT_TIER: &str = "KYC_1";
+const GAS_FOR_RESOLVE_MINT: Gas = Gas(30_000_000_000_000);
+const GAS_FOR_RESOLVE_COST: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_RESOLVE_RENEWAL: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_RESOLVE_UPDATE: Gas = Gas(20_000_000_000_000);
+/// Maximum number of tokens returned by one `tokens_with_status` call
+const MAX_TOKEN_STATUS_QUERY_LIMIT: u64 = 100;
+/// Maximum number of tokens scanned by one `tokens_with_status` call, to stay within the view gas limit
+const MAX_TOKEN_STATUS_SCAN: u64 = 500;
+/// Maximum length of the note of a revocation record
+const MAX_REVOCATION_NOTE_LENGTH: usize = 256;
+/// Maximum number of items in one batch update, each item logs an event and at most 100 logs are allowed
+const MAX_BATCH_SIZE: usize = 50;
+/// Gas reserved for each item of a batch update, the remaining items are skipped when running low on gas
+const GAS_PER_BATCH_ITEM: Gas = Gas(5_000_000_000_000);
+/// Balance kept on top of the storage staking by default when sending the balance out
+const DEFAULT_BALANCE_RESERVE: Balance = ONE_NEAR;
+/// Domain tag of mint authorization digests, so they cannot be confused with other hashes
+const AUTHORIZATION_DIGEST_DOMAIN: &str = "kycdao-ntnft/mint-authorization/v1";
+/// Domain tags of the messages signed by minters, so a signature is only valid for one type of payload
+const MINT_SIGNATURE_DOMAIN: &str = "kycdao-ntnft/mint-signature/v1";
+const RENEWAL_SIGNATURE_DOMAIN: &str = "kycdao-ntnft/renewal-signature/v1";
+/// Maximum length of the network name in a chain ID
+const MAX_CHAIN_NETWORK_LENGTH: usize = 32;
+/// Maximum length of the prefix of token IDs
+const MAX_TOKEN_ID_PREFIX_LENGTH: usize = 32;
+/// Gas kept by `upgrade` for deploying the code, the rest is passed to `migrate`
+const GAS_FOR_UPGRADE: Gas = Gas(20_000_000_000_000);
+/// Upper bound of the storage of the revocation record added to a token replaced by a mint:
+/// the record with a `Replaced by token <id>` note and the longest actor, plus a new history entry
+const MAX_REPLACEMENT_RECORD_STORAGE: StorageUsage = 300;
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug)]
 pub struct Status {
@@ -47,30 +85,309 @@ impl Default for Status {
     }
 }
 
-#[near_bindgen]
-#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
-pub struct OldKycdaoNTNFT {
-    tokens: NTNFT,
-    metadata: LazyOption<NTNFTContractMetadata>,
-    next_token_id: u128,
-    mint_authorizer: AccountId,
-    /// Tracks if token minting is authorized, stores metadata temporarily
-    authorized_token_metadata: LookupMap<Vec<u8>, TokenMetadata>,
-    /// Stores status for authorized (but not yet minted) tokens temporarily
-    authorized_statuses: UnorderedMap<Vec<u8>, Status>,
-    /// Stores status for minted tokens
-    token_statuses: UnorderedMap<TokenId, Status>,
-    /// The cost required for per year of subscription, expressed in USD
-    /// but with SUBSCRIPTION_COST_DECIMALS zeroes to allow for smaller values
-    subscription_cost_per_year: u32,
-    /// How many seconds need to be paid for on mint
-    authorized_seconds_to_pay: UnorderedMap<Vec<u8>, u32>,
-    /// Stores tier for authorized (but not yet minted) tokens
-    authorized_tiers: UnorderedMap<Vec<u8>, String>,
-    /// Stores tier for minted tokens
-    token_tiers: UnorderedMap<TokenId, String>,
-    /// Price feed for NEAR - USD conversions
-    native_usd_price_feed: PriceFeed,
+/// A verification tier in the tier registry
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TierInfo {
+    /// Tokens of a tier satisfy checks for any tier with the same or lower rank
+    pub rank: u32,
+    pub display_name: String,
+    /// The cost of subscription per year for the tier, expressed in USD with SUBSCRIPTION_COST_DECIMALS
+    /// decimals. The default subscription cost is used if not set
+    pub subscription_cost_per_year: Option<u32>,
+    /// Tokens of the tier can only be renewed with a signature from the mint authorizer
+    #[serde(default)]
+    pub requires_reverification: bool,
+}
+
+/// The payload signed off-chain with the signer key of a minter for `renew_subscription_with_signature`.
+/// The signed message is built as described at `SignedPayload`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+pub struct RenewalSignaturePayload {
+    /// Allows authorizing multiple renewals for the same token
+    pub auth_code: MintAuthorizationCode,
+    pub token_id: TokenId,
+    pub seconds: u32,
+    /// Deadline for using the signature (epoch time in seconds)
+    pub valid_until: u64,
+    /// The contract the signature is valid for
+    pub contract: AccountId,
+}
+
+/// Discounted subscription cost for subscriptions of at least `min_seconds`
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DurationPrice {
+    pub min_seconds: u32,
+    /// The cost of subscription per year, expressed in USD with SUBSCRIPTION_COST_DECIMALS decimals
+    pub subscription_cost_per_year: u32,
+}
+
+/// A row of the pricing table, as returned by `get_pricing_table`
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct TierPricing {
+    pub tier: String,
+    /// The cost of subscription per year without duration discounts
+    pub subscription_cost_per_year: u32,
+    /// Ordered by `min_seconds`
+    pub duration_prices: Vec<DurationPrice>,
+}
+
+/// Reason codes for revoking or reinstating a token
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RevocationReason {
+    Unspecified,
+    /// Suspected fraud or identity theft
+    Fraud,
+    /// The owner matched a sanctions list
+    Sanctions,
+    /// The documents used for the verification are no longer valid
+    DocumentsInvalid,
+    /// Requested by the token owner
+    OwnerRequest,
+    /// The earlier revocation was a mistake or its cause was resolved
+    Resolved,
+    Other,
+    /// Replaced by a newer token of the owner, see `TokenPolicy::RevokePrevious`
+    Superseded,
+}
+
+/// A change of the verified status of a token, kept in the revocation history of the token
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RevocationRecord {
+    /// false if the token was revoked, true if it was reinstated
+    pub verified: bool,
+    pub reason: RevocationReason,
+    pub note: Option<String>,
+    /// The account which changed the status
+    pub actor: AccountId,
+    /// block timestamp of the change (epoch time in seconds)
+    pub timestamp: u64,
+}
+
+/// Outcome of one item of a batch update
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchItemStatus {
+    Updated,
+    TokenNotFound,
+    /// Not processed because the call ran low on gas, the item can be sent again in a new batch
+    Skipped,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct BatchItemResult {
+    pub token_id: TokenId,
+    pub status: BatchItemStatus,
+}
+
+/// Formats of the URI returned by `token_uri`, the content ID of a token is stored in its `extra` metadata
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenUriTemplate {
+    /// `{base_uri}/{extra}.json`
+    BaseUri,
+    /// `ipfs://{extra}`
+    Ipfs,
+    /// `{base_uri}/ipfs/{extra}`, with an HTTPS IPFS gateway as the base URI
+    IpfsGateway,
+    /// `ar://{extra}`
+    Arweave,
+}
+
+/// A re-verification of an existing token authorized with `authorize_token_update`,
+/// which the token owner applies with `apply_token_update`
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct TokenUpdate {
+    pub auth_code: MintAuthorizationCode,
+    pub metadata: TokenMetadata,
+    pub tier: String,
+    /// epoch time in seconds
+    pub expiry: Option<u64>,
+    /// The number of seconds of subscription the owner pays for when applying the update
+    pub seconds_to_pay: u32,
+    /// The account which authorized the update and paid for its storage
+    pub authorizer: AccountId,
+    /// Refunded to the authorizer when the update is applied or cancelled
+    pub deposit: U128,
+}
+
+/// Parts of the contract which can be paused independently with `pause`
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PausableFeature {
+    /// `mint_with_code`, `mint_with_signature` and `apply_token_update`
+    Minting,
+    /// `authorize_mint_with_code`, `batch_authorize_mint_with_code` and `authorize_token_update`
+    Authorization,
+    /// `renew_subscription` and `renew_subscription_with_signature`
+    Renewal,
+    /// `send_balance_to_safe` and `send_balance_to`
+    Withdrawal,
+}
+
+/// How many tokens an account can hold, set by the owner with `set_token_policy`
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenPolicy {
+    /// Any number of tokens can be minted to the same account
+    Multiple,
+    /// A new token revokes the other verified tokens of the account
+    RevokePrevious,
+    /// A new token burns the other tokens of the account
+    BurnPrevious,
+    /// Mints are rejected if the account already has a valid token
+    RejectIfValid,
+}
+
+/// Number of tokens minted and burned, as returned by `get_token_counts`
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct TokenCounts {
+    pub minted: U128,
+    pub burned: U128,
+    /// Tokens which exist, the same as `ntnft_total_supply`
+    pub total_supply: U128,
+}
+
+/// Deposit paid by the authorizer for the storage of a pending authorization,
+/// refunded when the authorization is used, cancelled or purged
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct AuthorizationDeposit {
+    pub authorizer: AccountId,
+    pub amount: Balance,
+    /// The code and account of the authorization, to remove it from the digest index
+    pub auth_code: MintAuthorizationCode,
+    pub dst: AccountId,
+}
+
+/// The preimage of a mint authorization digest. It is Borsh serialized, so strings are length-prefixed
+/// and different codes and accounts cannot produce the same preimage
+#[derive(BorshSerialize)]
+struct AuthorizationDigestPayload {
+    domain: String,
+    chain_id: String,
+    contract: AccountId,
+    auth_code: MintAuthorizationCode,
+    dst: AccountId,
+    /// Unique for each stored authorization, 0 for signed authorizations. It only keeps the contract from
+    /// reusing the digest of a used or cancelled authorization for a new one with the same code and account.
+    /// It is not known to the user, so it does not protect against replaying anything the user submits
+    nonce: u64,
+}
+
+/// A mint authorization for `batch_authorize_mint_with_code`, with the arguments of `authorize_mint_with_code`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MintAuthorization {
+    pub auth_code: MintAuthorizationCode,
+    pub dst: AccountId,
+    pub metadata: TokenMetadata,
+    pub expiry: Option<u64>,
+    pub seconds_to_pay: u32,
+    pub verification_tier: String,
+    /// Optional deadline for using the authorization (epoch time in seconds)
+    pub valid_until: Option<u64>,
+}
+
+/// Outcome of one authorization of `batch_authorize_mint_with_code`
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchAuthorizationStatus {
+    Authorized,
+    /// A pending authorization with the same code and account exists, also within the same batch
+    AlreadyAuthorized,
+    UnknownTier,
+    /// Not processed because the call ran low on gas, the item can be sent again in a new batch
+    Skipped,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct BatchAuthorizationResult {
+    pub auth_code: MintAuthorizationCode,
+    pub dst: AccountId,
+    pub status: BatchAuthorizationStatus,
+}
+
+/// Criteria for `tokens_with_status`, tokens must match all of the set criteria
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct TokenStatusFilter {
+    /// true for verified tokens, false for revoked ones
+    pub verified: Option<bool>,
+    pub expired: Option<bool>,
+    /// Only tokens with an expiry before this (epoch time in seconds)
+    pub expiring_before: Option<u64>,
+    pub tier: Option<String>,
+}
+
+/// A token with its status, as returned by `tokens_with_status` and `kyc_status_of`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TokenStatusView {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub tier: String,
+    /// expiry timestamp (epoch time in seconds)
+    pub expiry: Option<u64>,
+    pub verified: bool,
+    pub valid: bool,
+}
+
+/// A page of `tokens_with_status`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TokenStatusPage {
+    pub tokens: Vec<TokenStatusView>,
+    /// The `from_index` of the next page, or None if all tokens were scanned
+    pub next_index: Option<U128>,
+}
+
+/// Summary of the KYC status of an account, as returned by `kyc_status_of`
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct KycStatus {
+    pub account_id: AccountId,
+    /// true if the account has at least one valid token
+    pub valid: bool,
+    pub tokens: Vec<TokenStatusView>,
+    /// The valid token with the highest ranked tier, the one expiring last if there are several
+    pub best_token: Option<TokenStatusView>,
+    /// Latest expiry of the valid tokens (epoch time in seconds), None if no valid token expires
+    pub latest_expiry: Option<u64>,
+}
+
+/// Result of `kyc_check`, meant for other contracts which gate their methods on a valid KYC token.
+/// When the account has no token satisfying the check, `valid` is false and the other fields
+/// describe the first token of the account (if any), so the caller can tell why the check failed.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct KycCheckResult {
+    pub valid: bool,
+    pub tier: Option<String>,
+    /// expiry timestamp (epoch time in seconds)
+    pub expiry: Option<u64>,
+    pub token_id: Option<TokenId>,
+}
+
+/// The payload signed off-chain with the signer key of a minter for `mint_with_signature`.
+/// The signed message is built as described at `SignedPayload`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+pub struct MintSignaturePayload {
+    pub auth_code: MintAuthorizationCode,
+    pub dst: AccountId,
+    pub metadata: TokenMetadata,
+    pub expiry: Option<u64>,
+    pub seconds_to_pay: u32,
+    pub tier: String,
+    /// The contract the signature is valid for
+    pub contract: AccountId,
+}
+
+/// A payload signed off-chain by a minter. The signed message is the keccak256 hash of the Borsh serialized
+/// domain of the payload type, the chain ID of the contract and the payload, one after the other
+pub trait SignedPayload: BorshSerialize {
+    const DOMAIN: &'static str;
+}
+
+impl SignedPayload for MintSignaturePayload {
+    const DOMAIN: &'static str = MINT_SIGNATURE_DOMAIN;
+}
+
+impl SignedPayload for RenewalSignaturePayload {
+    const DOMAIN: &'static str = RENEWAL_SIGNATURE_DOMAIN;
 }
 
 #[near_bindgen]
@@ -79,7 +396,8 @@ pub struct KycdaoNTNFT {
     tokens: NTNFT,
     metadata: LazyOption<NTNFTContractMetadata>,
     next_token_id: u128,
-    mint_authorizer: AccountId,
+    /// Accounts allowed to manage the contract, authorize mints, revoke tokens and set prices
+    roles: Roles,
     /// Tracks if token minting is authorized, stores metadata temporarily
     authorized_token_metadata: LookupMap<Vec<u8>, TokenMetadata>,
     /// Stores status for authorized (but not yet minted) tokens temporarily
@@ -96,7 +414,48 @@ pub struct KycdaoNTNFT {
     /// Stores tier for minted tokens
     token_tiers: UnorderedMap<TokenId, String>,
     /// Price feed for NEAR - USD conversions
-    native_usd_price_feed: PriceFeedMocked,
+    native_usd_price_feed: PriceFeed,
+    /// Public keys of the minters which sign mints and renewals
+    signer_keys: LookupMap<AccountId, PublicKey>,
+    /// Digests of the signatures which were already used for minting
+    used_mint_signatures: LookupSet<Vec<u8>>,
+    /// Stores the deadline for authorized (but not yet minted) tokens (epoch time in seconds)
+    authorized_deadlines: UnorderedMap<Vec<u8>, u64>,
+    /// The version of the stored state, used for migrations
+    storage_version: String,
+    /// Registry of the verification tiers which can be authorized
+    tiers: UnorderedMap<String, TierInfo>,
+    /// Subscription costs for longer subscriptions per tier, ordered by `min_seconds`
+    duration_prices: UnorderedMap<String, Vec<DurationPrice>>,
+    /// Hashes of the renewal signatures which were already used
+    used_renewal_signatures: LookupSet<Vec<u8>>,
+    /// Every revocation and reinstatement of tokens, in order. Kept after the token is burned
+    revocation_history: LookupMap<TokenId, Vec<RevocationRecord>>,
+    /// Storage deposits of pending authorizations
+    authorization_deposits: LookupMap<Vec<u8>, AuthorizationDeposit>,
+    /// The treasury account which `send_balance_to_safe` sends the balance to
+    safe_address: Option<AccountId>,
+    /// Balance kept in the contract on top of the storage staking when sending the balance out
+    balance_reserve: Balance,
+    /// Format of the URI returned by `token_uri`
+    token_uri_template: TokenUriTemplate,
+    /// The network of the contract, part of the mint authorization digests
+    chain_id: String,
+    /// Digest of the pending authorization for each account and code
+    authorization_digests: LookupMap<(AccountId, MintAuthorizationCode), Vec<u8>>,
+    /// The nonce of the last stored authorization
+    authorization_nonce: u64,
+    /// Prepended to the index of tokens to get their IDs, set once when the contract is initialized
+    token_id_prefix: String,
+    /// The number of burned tokens, token indices are never reused
+    burned_tokens: u128,
+    token_policy: TokenPolicy,
+    /// At most one pending update per token, a new authorization replaces the previous one
+    pending_token_updates: LookupMap<TokenId, TokenUpdate>,
+    paused_features: Vec<PausableFeature>,
+    /// Storage deposit paid by the owner of each token, refunded when the token is burned.
+    /// Tokens minted before v0.5.0 have none, their storage was paid by the contract
+    token_storage_deposits: LookupMap<TokenId, Balance>,
 }
 
 const DATA_IMAGE_SVG_ICON: &str = "data:image/svg+xml,%3csvg width='307' height='402' viewBox='0 0 307 402' fill='none' xmlns='http://www.w3.org/2000/svg'%3e%3cmask id='path-1-inside-1_1367_8934' fill='white'%3e%3cpath fill-rule='evenodd' clip-rule='evenodd' d='M305.999 26.6087L279.391 0L152.999 126.391L119.738 93.1305L93.1296 119.739L152.999 179.609L305.999 26.6087ZM0.00195312 26.6093L66.5238 93.1311L93.1325 66.5224L26.6107 0.000546972L0.00195312 26.6093Z'/%3e%3c/mask%3e%3cpath d='M279.391 0L282.219 -2.82843L279.391 -5.65685L276.562 -2.82843L279.391 0ZM305.999 26.6087L308.828 29.4372L311.656 26.6087L308.828 23.7803L305.999 26.6087ZM152.999 126.391L150.171 129.22L152.999 132.048L155.828 129.22L152.999 126.391ZM119.738 93.1305L122.567 90.3021L119.738 87.4737L116.91 90.3021L119.738 93.1305ZM93.1296 119.739L90.3012 116.911L87.4728 119.739L90.3012 122.568L93.1296 119.739ZM152.999 179.609L150.171 182.437L152.999 185.266L155.828 182.437L152.999 179.609ZM66.5238 93.1311L63.6953 95.9595L66.5238 98.7879L69.3522 95.9595L66.5238 93.1311ZM0.00195312 26.6093L-2.82647 23.7808L-5.6549 26.6093L-2.82647 29.4377L0.00195312 26.6093ZM93.1325 66.5224L95.9609 69.3508L98.7893 66.5224L95.9609 63.6939L93.1325 66.5224ZM26.6107 0.000546972L29.4391 -2.82788L26.6107 -5.65631L23.7822 -2.82788L26.6107 0.000546972ZM276.562 2.82843L303.171 29.4372L308.828 23.7803L282.219 -2.82843L276.562 2.82843ZM155.828 129.22L282.219 2.82843L276.562 -2.82843L150.171 123.563L155.828 129.22ZM116.91 95.959L150.171 129.22L155.828 123.563L122.567 90.3021L116.91 95.959ZM95.9581 122.568L122.567 95.959L116.91 90.3021L90.3012 116.911L95.9581 122.568ZM155.828 176.78L95.9581 116.911L90.3012 122.568L150.171 182.437L155.828 176.78ZM303.171 23.7803L150.171 176.78L155.828 182.437L308.828 29.4372L303.171 23.7803ZM69.3522 90.3026L2.83038 23.7808L-2.82647 29.4377L63.6953 95.9595L69.3522 90.3026ZM90.304 63.6939L63.6953 90.3026L69.3522 95.9595L95.9609 69.3508L90.304 63.6939ZM23.7822 2.82897L90.304 69.3508L95.9609 63.6939L29.4391 -2.82788L23.7822 2.82897ZM2.83038 29.4377L29.4391 2.82897L23.7822 -2.82788L-2.82647 23.7808L2.83038 29.4377Z' fill='%233D65F2' mask='url(%23path-1-inside-1_1367_8934)'/%3e%3cmask id='path-3-inside-2_1367_8934' fill='white'%3e%3cpath fill-rule='evenodd' clip-rule='evenodd' d='M305.999 248.298L279.391 221.69L152.999 348.081L119.738 314.82L93.1295 341.429L152.999 401.299L305.999 248.298ZM0.00195312 248.299L66.5238 314.821L93.1325 288.212L26.6107 221.69L0.00195312 248.299Z'/%3e%3c/mask%3e%3cpath d='M279.391 221.69L282.219 218.861L279.391 216.033L276.562 218.861L279.391 221.69ZM305.999 248.298L308.828 251.127L311.656 248.298L308.828 245.47L305.999 248.298ZM152.999 348.081L150.171 350.91L152.999 353.738L155.828 350.91L152.999 348.081ZM119.738 314.82L122.567 311.992L119.738 309.163L116.91 311.992L119.738 314.82ZM93.1295 341.429L90.3011 338.601L87.4727 341.429L90.3011 344.257L93.1295 341.429ZM152.999 401.299L150.171 404.127L152.999 406.955L155.828 404.127L152.999 401.299ZM66.5238 314.821L63.6953 317.649L66.5238 320.478L69.3522 317.649L66.5238 314.821ZM0.00195312 248.299L-2.82647 245.471L-5.6549 248.299L-2.82647 251.127L0.00195312 248.299ZM93.1325 288.212L95.9609 291.04L98.7893 288.212L95.9609 285.384L93.1325 288.212ZM26.6107 221.69L29.4391 218.862L26.6107 216.033L23.7822 218.862L26.6107 221.69ZM276.562 224.518L303.171 251.127L308.828 245.47L282.219 218.861L276.562 224.518ZM155.828 350.91L282.219 224.518L276.562 218.861L150.171 345.253L155.828 350.91ZM116.91 317.649L150.171 350.91L155.828 345.253L122.567 311.992L116.91 317.649ZM95.958 344.257L122.567 317.649L116.91 311.992L90.3011 338.601L95.958 344.257ZM155.828 398.47L95.958 338.601L90.3011 344.257L150.171 404.127L155.828 398.47ZM303.171 245.47L150.171 398.47L155.828 404.127L308.828 251.127L303.171 245.47ZM69.3522 311.992L2.83038 245.471L-2.82647 251.127L63.6953 317.649L69.3522 311.992ZM90.304 285.384L63.6953 311.992L69.3522 317.649L95.9609 291.04L90.304 285.384ZM23.7822 224.519L90.304 291.04L95.9609 285.384L29.4391 218.862L23.7822 224.519ZM2.83038 251.127L29.4391 224.519L23.7822 218.862L-2.82647 245.471L2.83038 251.127Z' fill='%233D65F2' mask='url(%23path-3-inside-2_1367_8934)'/%3e%3cmask id='path-5-inside-3_1367_8934' fill='white'%3e%3cpath fill-rule='evenodd' clip-rule='evenodd' d='M-9.15527e-05 135.407L26.6086 108.798L153 235.19L186.261 201.929L212.87 228.538L153 288.407L-9.15527e-05 135.407ZM306.002 135.408L239.48 201.929L212.871 175.321L279.393 108.799L306.002 135.408Z'/%3e%3c/mask%3e%3cpath d='M26.6086 108.798L23.7802 105.97L26.6086 103.142L29.437 105.97L26.6086 108.798ZM-9.15527e-05 135.407L-2.82852 138.236L-5.65694 135.407L-2.82852 132.579L-9.15527e-05 135.407ZM153 235.19L155.828 238.018L153 240.847L150.172 238.018L153 235.19ZM186.261 201.929L183.433 199.101L186.261 196.272L189.089 199.101L186.261 201.929ZM212.87 228.538L215.698 225.709L218.527 228.538L215.698 231.366L212.87 228.538ZM153 288.407L155.828 291.236L153 294.064L150.172 291.236L153 288.407ZM239.48 201.929L242.309 204.758L239.48 207.586L236.652 204.758L239.48 201.929ZM306.002 135.408L308.83 132.579L311.659 135.408L308.83 138.236L306.002 135.408ZM212.871 175.321L210.043 178.149L207.215 175.321L210.043 172.492L212.871 175.321ZM279.393 108.799L276.565 105.971L279.393 103.142L282.222 105.971L279.393 108.799ZM29.437 111.627L2.82834 138.236L-2.82852 132.579L23.7802 105.97L29.437 111.627ZM150.172 238.018L23.7802 111.627L29.437 105.97L155.828 232.361L150.172 238.018ZM189.089 204.757L155.828 238.018L150.172 232.361L183.433 199.101L189.089 204.757ZM210.041 231.366L183.433 204.757L189.089 199.101L215.698 225.709L210.041 231.366ZM150.172 285.579L210.041 225.709L215.698 231.366L155.828 291.236L150.172 285.579ZM2.82834 132.579L155.828 285.579L150.172 291.236L-2.82852 138.236L2.82834 132.579ZM236.652 199.101L303.174 132.579L308.83 138.236L242.309 204.758L236.652 199.101ZM215.7 172.492L242.309 199.101L236.652 204.758L210.043 178.149L215.7 172.492ZM282.222 111.627L215.7 178.149L210.043 172.492L276.565 105.971L282.222 111.627ZM303.174 138.236L276.565 111.627L282.222 105.971L308.83 132.579L303.174 138.236Z' fill='%233D65F2' mask='url(%23path-5-inside-3_1367_8934)'/%3e%3c/svg%3e";
@@ -120,13 +479,27 @@ enum StorageKey {
     TokenTiers,
     AuthorizedStatuses,
     TokenStatuses,
+    UsedMintSignatures,
+    AuthorizedDeadlines,
+    Roles,
+    RoleMembers { role: Role },
+    Tiers,
+    DurationPrices,
+    UsedRenewalSignatures,
+    RevocationHistory,
+    AuthorizationDeposits,
+    AuthorizationDigests,
+    PendingTokenUpdates,
+    TokensPerOwner { account_hash: Vec<u8> },
+    SignerKeys,
+    TokenStorageDeposits,
 }
 
 #[near_bindgen]
 impl KycdaoNTNFT {
     /// Initializes the contract with default metadata
     #[init]
-    pub fn new_default_meta(base_uri: String) -> Self {
+    pub fn new_default_meta(base_uri: String, chain_id: String) -> Self {
         Self::new(
             NTNFTContractMetadata {
                 spec: NTNFT_METADATA_SPEC.to_string(),
@@ -137,21 +510,28 @@ impl KycdaoNTNFT {
                 reference: None,
                 reference_hash: None,
             },
+            None,
+            chain_id,
         )
     }
 
     /// @dev Constructor sets the token metadata and the roles
-    /// @param base_uri String to prepend to token IDs
     /// @param metadata Name, symbol, etc.
+    /// @param token_id_prefix Prepended to the index of tokens to get their IDs, e.g. `kyc-` for `kyc-0`, `kyc-1`...
+    /// Cannot be changed later. Token IDs are the plain indices if not set
+    /// @param chain_id The network of the contract, e.g. `near:mainnet`, part of the mint authorization digests.
+    /// Cannot be changed later
     #[init]
-    pub fn new(metadata: NTNFTContractMetadata) -> Self {
+    pub fn new(metadata: NTNFTContractMetadata, token_id_prefix: Option<String>, chain_id: String) -> Self {
         assert!(!env::state_exists(), "Already initialized");
         metadata.assert_valid();
+        Self::assert_valid_chain_id(&chain_id);
+        let token_id_prefix = token_id_prefix.unwrap_or_default();
+        assert!(token_id_prefix.len() <= MAX_TOKEN_ID_PREFIX_LENGTH, "Token ID prefix is too long");
 
         let sender = env::signer_account_id();
 
-        let price_feed_addr = AccountId::from_str("priceoracle.testnet").expect("accountID should be valid");
-        let native_usd_price_feed = PriceFeedMocked::new(price_feed_addr);
+        let native_usd_price_feed = PriceFeed::default_for_chain(&chain_id);
 
         Self {
             tokens: NTNFT::new(
@@ -162,7 +542,7 @@ impl KycdaoNTNFT {
             ),
             metadata: LazyOption::new(StorageKey::Metadata, Some(&metadata)),
             next_token_id: 0,
-            mint_authorizer: sender.to_owned(),
+            roles: Self::initial_roles(&sender),
             authorized_token_metadata: LookupMap::new(StorageKey::AuthorizedTokenMetadata),
             authorized_statuses: UnorderedMap::new(StorageKey::AuthorizedStatuses),
             token_statuses: UnorderedMap::new(StorageKey::TokenStatuses),
@@ -171,89 +551,119 @@ impl KycdaoNTNFT {
             authorized_tiers: UnorderedMap::new(StorageKey::AuthorizedTiers),
             token_tiers: UnorderedMap::new(StorageKey::TokenTiers),
             native_usd_price_feed,
+            signer_keys: LookupMap::new(StorageKey::SignerKeys),
+            used_mint_signatures: LookupSet::new(StorageKey::UsedMintSignatures),
+            authorized_deadlines: UnorderedMap::new(StorageKey::AuthorizedDeadlines),
+            storage_version: VERSION.to_string(),
+            tiers: Self::initial_tiers(),
+            duration_prices: UnorderedMap::new(StorageKey::DurationPrices),
+            used_renewal_signatures: LookupSet::new(StorageKey::UsedRenewalSignatures),
+            revocation_history: LookupMap::new(StorageKey::RevocationHistory),
+            authorization_deposits: LookupMap::new(StorageKey::AuthorizationDeposits),
+            safe_address: None,
+            balance_reserve: DEFAULT_BALANCE_RESERVE,
+            token_uri_template: TokenUriTemplate::BaseUri,
+            chain_id,
+            authorization_digests: LookupMap::new(StorageKey::AuthorizationDigests),
+            authorization_nonce: 0,
+            token_id_prefix,
+            burned_tokens: 0,
+            token_policy: TokenPolicy::Multiple,
+            pending_token_updates: LookupMap::new(StorageKey::PendingTokenUpdates),
+            paused_features: Vec::new(),
+            token_storage_deposits: LookupMap::new(StorageKey::TokenStorageDeposits),
         }
     }
 
+    /// @dev Migrates the stored state from an older version of the contract to the current one.
+    /// Called by `upgrade` after the new code is deployed
+    /// @param chain_id The network of the contract, required when migrating from a version before v0.5.0.
+    /// Once stored it cannot be changed
     #[private]
     #[init(ignore_state)]
-    pub fn migrate() -> Self {
-        log!("Starting migration to v0.4.1...");
-
-        let old_state: OldKycdaoNTNFT = env::state_read().expect("failed");
-
-        log!("Old state read successfully");
-
-        let price_feed_addr = AccountId::from_str("priceoracle.testnet").expect("accountID should be valid");
-        let native_usd_price_feed = PriceFeedMocked::new(price_feed_addr);
-
-        log!("Defined price feed");
-
-        Self {
-            tokens: old_state.tokens,
-            metadata: old_state.metadata,
-            next_token_id: old_state.next_token_id,
-            mint_authorizer: old_state.mint_authorizer,
-            authorized_token_metadata: old_state.authorized_token_metadata,
-            authorized_statuses: old_state.authorized_statuses,
-            token_statuses: old_state.token_statuses,
-            subscription_cost_per_year: old_state.subscription_cost_per_year,
-            authorized_seconds_to_pay: old_state.authorized_seconds_to_pay,
-            authorized_tiers: old_state.authorized_tiers,
-            token_tiers: old_state.token_tiers,
-            native_usd_price_feed,
-        }
+    pub fn migrate(chain_id: Option<String>) -> Self {
+        migrations::migrate_state(chain_id)
     }
 
     /*****************
     Authorized Minting
     *****************/
     /// @dev Mint the token by using a signature from an authorized account
+    /// The signature must be made with the signer key of a minter over a `MintSignaturePayload`
+    /// and each (auth_code, dst) pair can only be used once.
+    /// The mint is completed in `resolve_mint_with_signature` with the price from the price feed
+    /// @param signer The minter whose key made the signature
     #[payable]
     pub fn mint_with_signature(
         &mut self,
-       _auth_code: MintAuthorizationCode,
-       _metadata: TokenMetadata,
-       _expiry: Option<u64>,
-       _seconds_to_pay: u32,
-       _tier: String,
-       _signature: Vec<u8>,
-    ) -> Token {
-        unimplemented!("Not yet implemented")
+        auth_code: MintAuthorizationCode,
+        metadata: TokenMetadata,
+        expiry: Option<u64>,
+        seconds_to_pay: u32,
+        tier: String,
+        signer: AccountId,
+        signature: Vec<u8>,
+    ) -> Promise {
+        self.assert_not_paused(PausableFeature::Minting);
+        let dst = env::predecessor_account_id();
+
+        log!("Checking minting signature for {} with code: {}", dst, auth_code);
+
+        let payload = MintSignaturePayload {
+            auth_code,
+            dst: dst.clone(),
+            metadata,
+            expiry,
+            seconds_to_pay,
+            tier,
+            contract: env::current_account_id(),
+        };
+        self.assert_valid_signer_signature(&signer, &payload, &signature);
+        self.assert_known_tier(&payload.tier);
+        self.assert_token_policy_allows_mint(&dst);
+
+        // Mark the signature as used, so it cannot be replayed. Signatures used before the current digest
+        // format are stored with the legacy digest
+        assert!(!self.used_mint_signatures.contains(&KycdaoNTNFT::legacy_digest(auth_code, &dst)), "Signature already used");
+        let digest = self.signature_digest(auth_code, &dst);
+        assert!(self.used_mint_signatures.insert(&digest), "Signature already used");
+
+        self.native_usd_price_feed.get_price_data().then(
+            Self::ext(env::current_account_id())
+                .with_attached_deposit(env::attached_deposit())
+                .with_static_gas(GAS_FOR_RESOLVE_MINT)
+                .resolve_mint_with_signature(payload)
+        )
     }
 
     /// @dev Mint the token by using a code from an authorized account
+    /// The mint is completed in `resolve_mint_with_code` with the price from the price feed
     #[payable]
-    pub fn mint_with_code(&mut self, auth_code: MintAuthorizationCode) -> Token {
+    pub fn mint_with_code(&mut self, auth_code: MintAuthorizationCode) -> Promise {
+        self.assert_not_paused(PausableFeature::Minting);
         let dst = env::predecessor_account_id();
         //let dst = env::signer_account_id();
 
-        let digest = KycdaoNTNFT::get_digest(auth_code, &dst);
-
         log!("Checking minting authorization for {} with code: {}", dst, auth_code);
 
-        // Get prefilled metadata, also remove digest so it cannot be used again
-        let metadata = self.authorized_token_metadata.remove(&digest).expect("Unauthorized code");
-        let status = self.authorized_statuses.remove(&digest).unwrap_or_default();
-        let tier = self.authorized_tiers.remove(&digest).unwrap_or(DEFAULT_TIER.to_string());
-        let seconds_to_pay = self.authorized_seconds_to_pay.remove(&digest).unwrap_or(0);
-
-        let cost = self.get_required_mint_cost_for_seconds_internal(seconds_to_pay);
-        if cost > 0 {
-            assert!(near_sdk::env::attached_deposit() >= cost, "Insufficient payment for minting");
-        }
-
-        let token_id = self.next_token_id;
-        self.next_token_id = self.next_token_id.checked_add(1).expect("Token ID overflow");
-        let token_id_str = token_id.to_string();
-
-        let token = self.tokens.internal_mint(token_id_str.clone(), dst, Some(metadata), Some(cost));
-        self.token_statuses.insert(&token_id_str, &status);
-        self.token_tiers.insert(&token_id_str, &tier);
+        let digest = self.pending_digest(auth_code, &dst).expect("Unauthorized code");
+        assert!(!self.authorization_expired(&digest), "Authorization expired");
+        self.assert_token_policy_allows_mint(&dst);
 
-        token
+        self.native_usd_price_feed.get_price_data().then(
+            Self::ext(env::current_account_id())
+                .with_attached_deposit(env::attached_deposit())
+                .with_static_gas(GAS_FOR_RESOLVE_MINT)
+                .resolve_mint_with_code(auth_code, dst)
+        )
     }
 
-    /// @dev Authorize the minting of a new token
+    /// @dev Authorize the minting of a new token. The attached deposit must cover the storage of the authorization,
+    /// the rest is refunded. The deposit is refunded when the authorization is used, cancelled or purged
+    /// @param valid_until Optional deadline for using the authorization (epoch time in seconds)
+    // The arguments are the JSON interface used by the backend, so they stay flat instead of being grouped in a struct
+    #[allow(clippy::too_many_arguments)]
+    #[payable]
     pub fn authorize_mint_with_code(
         &mut self,
         auth_code: MintAuthorizationCode,
@@ -262,494 +672,4654 @@ impl KycdaoNTNFT {
         expiry: Option<u64>,
         seconds_to_pay: u32,
         verification_tier: String,
+        valid_until: Option<u64>,
     ) {
-        self.assert_mint_authorizer();
-        let digest = KycdaoNTNFT::get_digest(auth_code, &dst);
+        self.assert_role(Role::Minter);
+        self.assert_not_paused(PausableFeature::Authorization);
+        self.assert_known_tier(&verification_tier);
 
         log!("Authorizing minting for {} with code: {}", dst, auth_code);
 
-        let authorized_opt = self.authorized_token_metadata.get(&digest);
-        assert!(authorized_opt.is_none(), "Code already authorized");
+        assert!(self.can_authorize(auth_code, &dst), "Code already authorized");
 
-        let new_status = Status {
-            verified: true,
+        let storage_cost = self.internal_authorize_mint(&MintAuthorization {
+            auth_code,
+            dst,
+            metadata,
             expiry,
-        };
-
-        self.authorized_token_metadata.insert(&digest, &metadata);
-        self.authorized_statuses.insert(&digest, &new_status);
-        self.authorized_seconds_to_pay.insert(&digest, &seconds_to_pay);
-        self.authorized_tiers.insert(&digest, &verification_tier);
-    }
-
-    /// @dev Returns the amount in NATIVE (yoctoNEAR) which is expected for a given mint which uses an auth code
-    /// @param auth_code The auth code used to authorize the mint
-    /// @param dst Address to mint the token to
-    pub fn get_required_mint_cost_for_code(&self, auth_code: MintAuthorizationCode, dst: AccountId) -> U128 {
-        let digest = KycdaoNTNFT::get_digest(auth_code, &dst);
-        let authorized_opt = self.authorized_token_metadata.get(&digest);
-        assert!(authorized_opt.is_some(), "Unauthorized code");
-        let authorized_seconds_to_pay = self.authorized_seconds_to_pay.get(&digest).unwrap_or(0);
-        self.get_required_mint_cost_for_seconds(authorized_seconds_to_pay)
+            seconds_to_pay,
+            verification_tier,
+            valid_until,
+        });
+        KycdaoNTNFT::charge_authorization_deposit(storage_cost);
     }
 
-    /*****************
-    Public interfaces
-    *****************/
-    pub fn version(&self) -> &str { "0.4.2" }
-
-    pub fn token_uri(&self, token_id: TokenId) -> String {
-        let token_metadata_store = self.tokens.token_metadata_by_id.as_ref().expect("Metadata not supported");
-        let token_metadata: TokenMetadata = token_metadata_store.get(&token_id).expect("Token not found");
-
-        let contract_metadata = self.metadata.get().expect("Metadata not supported");
-        let base_uri = contract_metadata.base_uri.expect("Missing base_uri");
-        assert!(!base_uri.is_empty(), "Empty base_uri");
+    /// @dev Authorize the minting of many tokens in one transaction, like `authorize_mint_with_code`.
+    /// Invalid or already authorized entries are reported in the result instead of failing the whole batch.
+    /// The attached deposit must cover the storage of the new authorizations, the rest is refunded.
+    /// The deposit of each authorization is refunded when it is used, cancelled or purged
+    /// @param authorizations At most MAX_BATCH_SIZE authorizations
+    /// @return The outcome for each authorization, in the order of `authorizations`
+    #[payable]
+    pub fn batch_authorize_mint_with_code(&mut self, authorizations: Vec<MintAuthorization>) -> Vec<BatchAuthorizationResult> {
+        self.assert_role(Role::Minter);
+        self.assert_not_paused(PausableFeature::Authorization);
+        KycdaoNTNFT::assert_valid_batch_size(authorizations.len());
+        let mut storage_cost = 0;
 
-        format!("{}/{}.json", base_uri, token_metadata.extra.expect("Missing token extra data"))
-    }
+        let results: Vec<BatchAuthorizationResult> = authorizations.into_iter().map(|authorization| {
+            let status = if env::prepaid_gas() - env::used_gas() < GAS_PER_BATCH_ITEM {
+                BatchAuthorizationStatus::Skipped
+            } else if self.tiers.get(&authorization.verification_tier).is_none() {
+                BatchAuthorizationStatus::UnknownTier
+            } else if !self.can_authorize(authorization.auth_code, &authorization.dst) {
+                BatchAuthorizationStatus::AlreadyAuthorized
+            } else {
+                storage_cost += self.internal_authorize_mint(&authorization);
+                BatchAuthorizationStatus::Authorized
+            };
+            BatchAuthorizationResult { auth_code: authorization.auth_code, dst: authorization.dst, status }
+        }).collect();
 
-    /// Check the validity of a specific token
-    pub fn token_is_valid(&self, token_id: TokenId) -> bool {
-        self.tokens.owner_by_id.get(&token_id).expect("Token not found");
-        self.token_statuses.get(&token_id).unwrap_or_default().is_valid()
+        KycdaoNTNFT::charge_authorization_deposit(storage_cost);
+        results
     }
 
-    /// Check the expiry of the token (epoch time in seconds) - None means it never expires
-    pub fn token_expiry(&self, token_id: TokenId) -> Option<u64> {
-        self.tokens.owner_by_id.get(&token_id).expect("Token not found");
-        self.token_statuses.get(&token_id).unwrap_or_default().expiry
-    }
+    /// @dev Cancel a pending mint authorization and free its storage
+    pub fn cancel_mint_authorization(&mut self, auth_code: MintAuthorizationCode, dst: AccountId) {
+        self.assert_role(Role::Minter);
+        let digest = self.pending_digest(auth_code, &dst).expect("Unauthorized code");
+        self.remove_authorization(&digest);
 
-    /// Get the verification tier of a specific token
-    pub fn token_tier(&self, token_id: TokenId) -> String {
-        self.tokens.owner_by_id.get(&token_id).expect("Token not found");
-        self.token_tiers.get(&token_id).unwrap_or(DEFAULT_TIER.to_string())
+        MintAuthorizationCancelled { auth_code, dst: &dst }.emit();
     }
 
-    /// Check if an account has any valid tokens
-    pub fn has_valid_token(&self, address: AccountId) -> bool {
-        match self.tokens.tokens_per_owner.as_ref().expect("enumeration extension in use").get(&address) {
-            Some(token_ids) => {
-                for token_id in token_ids.iter() {
-                    let status = self.token_statuses.get(&token_id).unwrap_or_default();
-                    if status.is_valid() {
-                        return true;
-                    }
-                }
-                false
+    /// @dev Remove expired mint authorizations to free their storage, scanning at most `limit` authorizations
+    /// with a deadline. A removed authorization is replaced by the last one, so the scan continues at the same index.
+    /// Continue from the returned index until it is None.
+    /// @param from_index Index to start scanning from, defaults to 0
+    /// @param limit Number of authorizations to scan
+    /// @return The `from_index` of the next call, or None if all authorizations were scanned
+    pub fn purge_expired_authorizations(&mut self, from_index: Option<U128>, limit: u32) -> Option<U128> {
+        assert!(limit > 0, "Cannot provide limit of 0");
+        let mut index = from_index.map(|index| index.0 as u64).unwrap_or(0);
+        let mut purged = vec![];
+        let mut purged_legacy = vec![];
+        for _ in 0..limit {
+            let digest = match self.authorized_deadlines.keys_as_vector().get(index) {
+                Some(digest) => digest,
+                None => break,
+            };
+            if !self.authorization_expired(&digest) {
+                index += 1;
+                continue;
             }
-            None => false,
+            // Authorizations made before v0.5.0 have no deposit record with their code and account
+            match self.authorization_deposits.get(&digest) {
+                Some(deposit) => purged.push((deposit.auth_code, deposit.dst)),
+                None => purged_legacy.push(LegacyMintAuthorizationPurged { digest: KycdaoNTNFT::hex_encode(&digest) }),
+            }
+            self.remove_authorization(&digest);
         }
-    }
-
-    /// @dev Returns the amount in NATIVE (yoctoNEAR) which is expected for a given amount of subscription time in seconds
-    /// @param seconds The number of seconds of subscription time to calculate the cost for
-    pub fn get_required_mint_cost_for_seconds(&self, seconds: u32) -> U128 {
-        U128(self.get_required_mint_cost_for_seconds_internal(seconds))
-    }
 
-    /// Returns the cost for subscription per year in USD, to SUBSCRIPTION_COST_DECIMALS decimal places
-    pub fn get_subscription_cost_per_year_usd(&self) -> u32 {
-        self.subscription_cost_per_year
-    }
+        let events: Vec<MintAuthorizationCancelled> = purged.iter()
+            .map(|(auth_code, dst)| MintAuthorizationCancelled { auth_code: *auth_code, dst })
+            .collect();
+        if !events.is_empty() {
+            MintAuthorizationCancelled::emit_many(&events);
+        }
+        if !purged_legacy.is_empty() {
+            LegacyMintAuthorizationPurged::emit_many(&purged_legacy);
+        }
 
-    pub fn get_mint_authorizer(&self) -> AccountId {
-        self.mint_authorizer.clone()
+        if index < self.authorized_deadlines.len() { Some(U128(index as u128)) } else { None }
     }
 
-    /// @notice Get the price feed address used for native - USD conversions
-    pub fn get_price_feed(&self) -> AccountId {
-        self.native_usd_price_feed.price_feed_address()
-    }
+    /// @dev Move pending authorizations made before the current digest format to the new format.
+    /// The old digests cannot be reversed, so the codes and accounts have to be provided.
+    /// Until then, the old authorizations can still be used with their old digest
+    /// @param authorizations Pairs of code and account, at most MAX_BATCH_SIZE
+    /// @return The number of authorizations moved
+    pub fn migrate_legacy_authorizations(&mut self, authorizations: Vec<(MintAuthorizationCode, AccountId)>) -> u32 {
+        self.assert_role(Role::Minter);
+        KycdaoNTNFT::assert_valid_batch_size(authorizations.len());
 
-    /// @notice Get the last price on the price feed
-    pub fn get_latest_price(&self) -> (u32, u8) {
-        self.native_usd_price_feed.latest_price()
-    }
+        let mut migrated = 0;
+        for (auth_code, dst) in authorizations {
+            let legacy_digest = KycdaoNTNFT::legacy_digest(auth_code, &dst);
+            if self.authorization_digests.contains_key(&(dst.clone(), auth_code)) {
+                continue;
+            }
+            let metadata = match self.authorized_token_metadata.remove(&legacy_digest) {
+                Some(metadata) => metadata,
+                None => continue,
+            };
+            let status = self.authorized_statuses.remove(&legacy_digest).unwrap_or_default();
+            let seconds_to_pay = self.authorized_seconds_to_pay.remove(&legacy_digest).unwrap_or(0);
+            let tier = self.authorized_tiers.remove(&legacy_digest).unwrap_or(DEFAULT_TIER.to_string());
+            let deadline = self.authorized_deadlines.remove(&legacy_digest);
 
-    /*****************
-    Mint authorizer functions
-    *****************/
-    /// Check if the token is verified or not (revoked)
-    pub fn token_is_verified(&self, token_id: TokenId) -> bool {
-        self.assert_mint_authorizer();
-        self.tokens.owner_by_id.get(&token_id).expect("Token not found");
-        self.token_statuses.get(&token_id).unwrap_or_default().verified
-    }
+            let digest = self.next_authorization_digest(auth_code, &dst);
+            self.store_authorization(&digest, &MintAuthorization {
+                auth_code,
+                dst: dst.clone(),
+                metadata,
+                expiry: status.expiry,
+                seconds_to_pay,
+                verification_tier: tier,
+                valid_until: deadline,
+            });
+            // The storage of legacy authorizations was paid by the contract, so there is nothing to refund
+            self.authorization_deposits.insert(&digest, &AuthorizationDeposit {
+                authorizer: env::current_account_id(),
+                amount: 0,
+                auth_code,
+                dst,
+            });
+            migrated += 1;
+        }
 
-    pub fn set_verified_token(&mut self, token_id: TokenId, verified: bool) {
-        self.assert_mint_authorizer();
-        self.tokens.owner_by_id.get(&token_id).expect("Token not found");
-        let mut status = self.token_statuses.get(&token_id).unwrap_or_default();
-        status.verified = verified;
-        self.token_statuses.insert(&token_id, &status);
+        log!("Migrated {} legacy authorizations", migrated);
+        migrated
     }
 
-    /// Update the expiry of a token - expects epoch time in seconds
-    pub fn update_expiry(&mut self, token_id: TokenId, expiry: Option<u64>) {
-        self.assert_mint_authorizer();
-        self.tokens.owner_by_id.get(&token_id).expect("Token not found");
-        let mut status = self.token_statuses.get(&token_id).unwrap_or_default();
-        status.expiry = expiry;
-        self.token_statuses.insert(&token_id, &status);
+    /// @dev Returns the amount in NATIVE (yoctoNEAR) which is expected for a given mint which uses an auth code
+    /// Resolves with the current price from the price feed, so it cannot be called as a view
+    /// @param auth_code The auth code used to authorize the mint
+    /// @param dst Address to mint the token to
+    pub fn get_required_mint_cost_for_code(&self, auth_code: MintAuthorizationCode, dst: AccountId) -> Promise {
+        let digest = self.pending_digest(auth_code, &dst).expect("Unauthorized code");
+        let authorized_seconds_to_pay = self.authorized_seconds_to_pay.get(&digest).unwrap_or(0);
+        let tier = self.authorized_tiers.get(&digest).unwrap_or(DEFAULT_TIER.to_string());
+        self.get_required_mint_cost(authorized_seconds_to_pay, tier)
     }
 
     /*****************
-    Owner functions
+    Subscription renewal
     *****************/
-    /// @notice Set new base URI for references
-    /// @param base_uri String to prepend references
-    pub fn set_base_uri(&mut self, base_uri: String) {
-        self.assert_owner();
-        let mut metadata = self.metadata.get().expect("Metadata not supported");
-        metadata.base_uri = Some(base_uri);
-    }
+    /// @dev Pay for extending the subscription of a token, the excess deposit is refunded
+    /// The subscription is extended from the current expiry, or from now if the token already expired
+    /// @param token_id The token to renew, must be owned by the predecessor
+    /// @param seconds The number of seconds to extend the subscription with
+    #[payable]
+    pub fn renew_subscription(&mut self, token_id: TokenId, seconds: u32) -> Promise {
+        self.assert_not_paused(PausableFeature::Renewal);
+        self.assert_renewable(&token_id);
+        let tier = self.token_tiers.get(&token_id).unwrap_or(DEFAULT_TIER.to_string());
+        let requires_reverification = self.tiers.get(&tier).map(|info| info.requires_reverification).unwrap_or(false);
+        assert!(!requires_reverification, "Tier requires re-verification for renewal");
 
-    /// @notice Set the cost of subscription per yer
-    /// @param value u32 the cost of subscription per year in USD
-    pub fn set_subscription_cost(&mut self, value: u32) {
-        self.assert_owner();
-        self.subscription_cost_per_year = value;
+        self.internal_renew_subscription(token_id, seconds, None)
     }
 
-    pub fn set_mint_authorizer(&mut self, authorizer: AccountId) {
-        self.assert_owner();
-        self.mint_authorizer = authorizer;
-    }
+    /// @dev Pay for extending the subscription of a token with a signature from a minter,
+    /// for tiers which require re-verification. The signature must be made with the signer key of the minter
+    /// over a `RenewalSignaturePayload` and can only be used once.
+    /// @param token_id The token to renew, must be owned by the predecessor
+    /// @param seconds The number of seconds to extend the subscription with
+    /// @param auth_code The code in the signed payload
+    /// @param valid_until The deadline in the signed payload (epoch time in seconds)
+    /// @param signer The minter whose key made the signature
+    /// @param signature The ed25519 signature of the payload
+    #[payable]
+    pub fn renew_subscription_with_signature(
+        &mut self,
+        token_id: TokenId,
+        seconds: u32,
+        auth_code: MintAuthorizationCode,
+        valid_until: u64,
+        signer: AccountId,
+        signature: Vec<u8>,
+    ) -> Promise {
+        self.assert_not_paused(PausableFeature::Renewal);
+        self.assert_renewable(&token_id);
+        // Compared in seconds, like the deadlines of mint authorizations
+        assert!(block_timestamp() / u64::pow(10, 9) < valid_until, "Signature expired");
 
-    /// for retrieving all payments sent to contract
-    pub fn send_balance_to(&self, recipient: AccountId) {
-        self.assert_owner();
-        Promise::new(recipient).transfer(env::account_balance());
-    }
+        let payload = RenewalSignaturePayload {
+            auth_code,
+            token_id: token_id.clone(),
+            seconds,
+            valid_until,
+            contract: env::current_account_id(),
+        };
+        self.assert_valid_signer_signature(&signer, &payload, &signature);
 
-    /// @notice Set the price feed address used for native - USD conversions
-    /// @param address Address the address of the price feed
-    pub fn set_price_feed(&mut self, address: AccountId) {
-        self.assert_owner();
-        self.native_usd_price_feed = PriceFeedMocked::new(address);
-    }
+        // Mark the signature as used, so it cannot be replayed
+        let digest = KycdaoNTNFT::signed_message(&self.chain_id, &payload);
+        assert!(self.used_renewal_signatures.insert(&digest), "Signature already used");
 
-    /// @notice Set the last price on the price feed
-    /// @param price USD Price
-    /// @parma decimals Number of decimals
-    pub fn set_latest_price(&mut self, price: u32, decimals: u8) {
-        self.assert_owner();
-        self.native_usd_price_feed.set_latest_price(price, decimals)
+        self.internal_renew_subscription(token_id, seconds, Some(digest))
     }
 
     /*****************
-    HELPERS
+    Token updates
     *****************/
-    fn get_digest(auth_code: MintAuthorizationCode, dst: &AccountId) -> Vec<u8> {
-        let contract_addr = env::current_account_id();
-        keccak256(format!("{}{}{}", auth_code, dst, contract_addr).as_bytes())
-    }
+    /// @dev Authorize a re-verification of an existing token, e.g. an upgrade to a higher tier, which the token owner
+    /// applies with `apply_token_update`. A new authorization for the same token replaces the pending one.
+    /// The attached deposit must cover the storage of the authorization, the rest is refunded.
+    /// The deposit is refunded when the update is applied, replaced or cancelled
+    /// @param token_id The token to update
+    /// @param auth_code The code the token owner has to provide to `apply_token_update`
+    /// @param new_metadata Replaces the metadata of the token, `updated_at` is set when the update is applied
+    /// @param new_tier Replaces the verification tier of the token
+    /// @param new_expiry Replaces the expiry of the token (epoch time in seconds)
+    /// @param seconds_to_pay The number of seconds of subscription of the new tier the owner has to pay for
+    #[payable]
+    pub fn authorize_token_update(
+        &mut self,
+        token_id: TokenId,
+        auth_code: MintAuthorizationCode,
+        new_metadata: TokenMetadata,
+        new_tier: String,
+        new_expiry: Option<u64>,
+        seconds_to_pay: u32,
+    ) {
+        self.assert_role(Role::Minter);
+        self.assert_not_paused(PausableFeature::Authorization);
+        self.assert_known_tier(&new_tier);
+        self.tokens.owner_by_id.get(&token_id).expect("Token not found");
+        self.internal_remove_token_update(&token_id);
 
-    fn assert_mint_authorizer(&self) {
-        assert_eq!(env::predecessor_account_id(), self.get_mint_authorizer(), "Predecessor must be Mint Authorizer");
-    }
+        let initial_storage_usage = env::storage_usage();
+        let mut update = TokenUpdate {
+            auth_code,
+            metadata: new_metadata,
+            tier: new_tier,
+            expiry: new_expiry,
+            seconds_to_pay,
+            authorizer: env::predecessor_account_id(),
+            deposit: U128(0),
+        };
+        self.pending_token_updates.insert(&token_id, &update);
+        let storage_cost = env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage_usage);
+        update.deposit = U128(storage_cost);
+        self.pending_token_updates.insert(&token_id, &update);
 
-    /// Returns the amount in NATIVE (yoctoNEAR) which is expected when minting per year of subscription
-    fn get_subscription_price_per_year_native(&self) -> Balance {
-        let (price, decimals) = self.native_usd_price_feed.latest_price();
-        let decimal_convert = u128::pow(10, (YOCTONEAR_TO_NATIVE_DECIMALS - SUBSCRIPTION_COST_DECIMALS + decimals) as u32);
-        return (self.subscription_cost_per_year as u128 * decimal_convert) / price as u128
+        TokenUpdateAuthorized {
+            token_id: &token_id,
+            auth_code,
+            tier: &update.tier,
+            expiry: update.expiry,
+            seconds_to_pay,
+        }.emit();
+        KycdaoNTNFT::charge_authorization_deposit(storage_cost);
     }
 
-    pub fn get_required_mint_cost_for_seconds_internal(&self, seconds: u32) -> Balance {
-        (self.get_subscription_price_per_year_native() * seconds as u128) / SECS_IN_YEAR
+    /// @dev Cancel the pending update of a token and refund its storage deposit to the authorizer
+    pub fn cancel_token_update(&mut self, token_id: TokenId) {
+        self.assert_role(Role::Minter);
+        self.internal_remove_token_update(&token_id).expect("No pending update for the token");
     }
-}
 
-near_contract_standards::impl_ntnft_core!(KycdaoNTNFT, tokens);
-near_contract_standards::impl_ntnft_enumeration!(KycdaoNTNFT, tokens);
+    /// @dev Apply the pending update of a token owned by the predecessor. The attached deposit must cover
+    /// `seconds_to_pay` of the new tier and any additional storage, the rest is refunded.
+    /// The update is completed in `resolve_token_update` with the price from the price feed
+    /// @param token_id The token to update
+    /// @param auth_code The code of the update given by the authorizer
+    #[payable]
+    pub fn apply_token_update(&mut self, token_id: TokenId, auth_code: MintAuthorizationCode) -> Promise {
+        self.assert_not_paused(PausableFeature::Minting);
+        let owner_id = self.tokens.owner_by_id.get(&token_id).expect("Token not found");
+        assert_eq!(owner_id, env::predecessor_account_id(), "Only the token owner can apply the update");
+        let update = self.pending_token_updates.get(&token_id).expect("No pending update for the token");
+        assert_eq!(update.auth_code, auth_code, "Unauthorized code");
 
-impl Ownable for KycdaoNTNFT {
-    fn get_owner(&self) -> AccountId {
-        self.tokens.owner_id.clone()
+        self.native_usd_price_feed.get_price_data().then(
+            Self::ext(env::current_account_id())
+                .with_attached_deposit(env::attached_deposit())
+                .with_static_gas(GAS_FOR_RESOLVE_UPDATE)
+                .resolve_token_update(token_id, auth_code, owner_id)
+        )
     }
 
-    fn set_owner(&mut self, owner: AccountId) {
-        self.assert_owner();
-        self.tokens.owner_id = owner;
+    /// @dev Returns the amount in NATIVE (yoctoNEAR) which is expected for applying the pending update of a token,
+    /// excluding storage. Resolves with the current price from the price feed, so it cannot be called as a view
+    pub fn get_required_update_cost(&self, token_id: TokenId) -> Promise {
+        let update = self.pending_token_updates.get(&token_id).expect("No pending update for the token");
+        self.get_required_mint_cost(update.seconds_to_pay, update.tier)
     }
-}
 
-#[near_bindgen]
-impl NTNFTMetadataProvider for KycdaoNTNFT {
-    fn ntnft_metadata(&self) -> NTNFTContractMetadata {
-        self.metadata.get().unwrap()
+    /*****************
+    Burning
+    *****************/
+    /// @dev Burn a token owned by the predecessor, the storage deposit paid by the owner is refunded
+    /// @param token_id The token to burn
+    pub fn burn(&mut self, token_id: TokenId) {
+        let owner_id = self.tokens.owner_by_id.get(&token_id).expect("Token not found");
+        assert_eq!(owner_id, env::predecessor_account_id(), "Only the token owner can burn the token");
+        self.internal_burn(&token_id, None, None);
     }
-}
 
-#[cfg(not(target_arch = "wasm32"))]
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /*****************
+    Price feed callbacks
+    *****************/
+    /// @dev Finishes `mint_with_code` with the received price, refunds the deposit on failure
+    #[private]
+    #[payable]
+    pub fn resolve_mint_with_code(
+        &mut self,
+        auth_code: MintAuthorizationCode,
+        dst: AccountId,
+        #[callback_result] price_data: Result<PriceData, PromiseError>,
+    ) -> Option<Token> {
+        let price = match self.resolve_price(price_data) {
+            Ok(price) => price,
+            Err(err) => return self.refund_failed_mint(dst, &err),
+        };
+
+        // The code might have been used while waiting for the price
+        let digest = match self.pending_digest(auth_code, &dst) {
+            Some(digest) => digest,
+            None => return self.refund_failed_mint(dst, "Unauthorized code"),
+        };
+        if self.authorization_expired(&digest) {
+            return self.refund_failed_mint(dst, "Authorization expired");
+        }
+
+        let seconds_to_pay = self.authorized_seconds_to_pay.get(&digest).unwrap_or(0);
+        let tier = self.authorized_tiers.get(&digest).unwrap_or(DEFAULT_TIER.to_string());
+        let cost = match self.get_required_mint_cost_for_seconds_internal(seconds_to_pay, &tier, price) {
+            Ok(cost) => cost,
+            Err(err) => return self.refund_failed_mint(dst, &err),
+        };
+        if env::attached_deposit() < cost {
+            return self.refund_failed_mint(dst, "Insufficient payment for minting");
+        }
+
+        // Get prefilled metadata
+        let metadata = self.authorized_token_metadata.get(&digest).expect("Unauthorized code");
+        let status = self.authorized_statuses.get(&digest).unwrap_or_default();
+
+        let token = self.internal_mint_kyc_token(dst, metadata, status, tier, cost);
+        if token.is_some() {
+            // Remove digest so it cannot be used again
+            self.remove_authorization(&digest);
+        }
+
+        token
+    }
+
+    /// @dev Finishes `mint_with_signature` with the received price, refunds the deposit on failure
+    #[private]
+    #[payable]
+    pub fn resolve_mint_with_signature(
+        &mut self,
+        payload: MintSignaturePayload,
+        #[callback_result] price_data: Result<PriceData, PromiseError>,
+    ) -> Option<Token> {
+        let digest = self.signature_digest(payload.auth_code, &payload.dst);
+        let cost = self.resolve_price(price_data).and_then(|price| {
+            let cost = self.get_required_mint_cost_for_seconds_internal(payload.seconds_to_pay, &payload.tier, price)?;
+            if env::attached_deposit() < cost {
+                return Err("Insufficient payment for minting".to_string());
+            }
+            Ok(cost)
+        });
+
+        let token = match cost {
+            Ok(cost) => {
+                let status = Status {
+                    verified: true,
+                    expiry: payload.expiry,
+                };
+                self.internal_mint_kyc_token(payload.dst, payload.metadata, status, payload.tier, cost)
+            }
+            Err(err) => self.refund_failed_mint(payload.dst, &err),
+        };
+
+        if token.is_none() {
+            // Release the signature, so the mint can be retried
+            self.used_mint_signatures.remove(&digest);
+        }
+
+        token
+    }
+
+    /// @dev Finishes `apply_token_update` with the received price, refunds the deposit on failure
+    /// @return The updated token
+    #[private]
+    #[payable]
+    pub fn resolve_token_update(
+        &mut self,
+        token_id: TokenId,
+        auth_code: MintAuthorizationCode,
+        payer: AccountId,
+        #[callback_result] price_data: Result<PriceData, PromiseError>,
+    ) -> Option<Token> {
+        let price = match self.resolve_price(price_data) {
+            Ok(price) => price,
+            Err(err) => return self.refund_failed_update(payer, &err),
+        };
+
+        // The update might have been replaced or cancelled, or the token transferred or burned, while waiting for the price
+        let update = match self.pending_token_updates.get(&token_id) {
+            Some(update) if update.auth_code == auth_code => update,
+            _ => return self.refund_failed_update(payer, "Unauthorized code"),
+        };
+        if self.tokens.owner_by_id.get(&token_id).as_ref() != Some(&payer) {
+            return self.refund_failed_update(payer, "Only the token owner can apply the update");
+        }
+        let cost = match self.get_required_mint_cost_for_seconds_internal(update.seconds_to_pay, &update.tier, price) {
+            Ok(cost) => cost,
+            Err(err) => return self.refund_failed_update(payer, &err),
+        };
+
+        // The stored entries are kept as they are, so a failed update can restore missing entries as missing
+        let token_metadata_by_id = self.tokens.token_metadata_by_id.as_mut().expect("Metadata not supported");
+        let stored_metadata = token_metadata_by_id.get(&token_id);
+        let stored_tier = self.token_tiers.get(&token_id);
+        let stored_status = self.token_statuses.get(&token_id);
+        let old_tier = stored_tier.clone().unwrap_or(DEFAULT_TIER.to_string());
+        let old_status = self.token_statuses.get(&token_id).unwrap_or_default();
+
+        let initial_storage_usage = env::storage_usage();
+        let mut metadata = update.metadata.clone();
+        // NEP-177 timestamps are in milliseconds
+        metadata.updated_at = Some((block_timestamp() / u64::pow(10, 6)).to_string());
+        token_metadata_by_id.insert(&token_id, &metadata);
+        self.token_tiers.insert(&token_id, &update.tier);
+        self.token_statuses.insert(&token_id, &Status { expiry: update.expiry, ..old_status });
+
+        let storage_cost = env::storage_byte_cost() * Balance::from(env::storage_usage().saturating_sub(initial_storage_usage));
+        let required_deposit = cost + storage_cost;
+        let deposit = env::attached_deposit();
+        if deposit < required_deposit {
+            match stored_metadata {
+                Some(metadata) => token_metadata_by_id.insert(&token_id, &metadata),
+                None => token_metadata_by_id.remove(&token_id),
+            };
+            match stored_tier {
+                Some(tier) => self.token_tiers.insert(&token_id, &tier),
+                None => self.token_tiers.remove(&token_id),
+            };
+            match stored_status {
+                Some(status) => self.token_statuses.insert(&token_id, &status),
+                None => self.token_statuses.remove(&token_id),
+            };
+            return self.refund_failed_update(payer, &format!("Must attach {} yoctoNEAR to cover the subscription and storage", required_deposit));
+        }
+
+        if storage_cost > 0 {
+            let storage_deposit = self.token_storage_deposits.get(&token_id).unwrap_or(0);
+            self.token_storage_deposits.insert(&token_id, &(storage_deposit + storage_cost));
+        }
+        self.internal_remove_token_update(&token_id);
+        let refund = deposit - required_deposit;
+        if refund > 0 {
+            Promise::new(payer.clone()).transfer(refund);
+        }
+
+        if old_tier != update.tier {
+            TierChanged { token_id: &token_id, old_tier: &old_tier, new_tier: &update.tier }.emit();
+        }
+        if old_status.expiry != update.expiry {
+            ExpiryUpdated { token_id: &token_id, old_expiry: old_status.expiry, new_expiry: update.expiry }.emit();
+        }
+        TokenUpdated { token_id: &token_id, owner_id: &payer, tier: &update.tier, expiry: update.expiry, cost: U128(cost) }.emit();
+
+        self.ntnft_token(token_id)
+    }
+
+    /// @dev Finishes the renewal of a subscription with the received price, refunds the deposit on failure
+    /// @return The new expiry of the token
+    #[private]
+    #[payable]
+    pub fn resolve_renew_subscription(
+        &mut self,
+        token_id: TokenId,
+        seconds: u32,
+        payer: AccountId,
+        signature_digest: Option<Vec<u8>>,
+        #[callback_result] price_data: Result<PriceData, PromiseError>,
+    ) -> Option<u64> {
+        let tier = self.token_tiers.get(&token_id).unwrap_or(DEFAULT_TIER.to_string());
+        let cost = self.resolve_price(price_data).and_then(|price| {
+            let cost = self.get_required_mint_cost_for_seconds_internal(seconds, &tier, price)?;
+            if env::attached_deposit() < cost {
+                return Err("Insufficient payment for renewal".to_string());
+            }
+            Ok(cost)
+        });
+
+        // The token might have been revoked while waiting for the price
+        let status = self.token_statuses.get(&token_id);
+        let cost = cost.and_then(|cost| match status {
+            Some(Status { verified: true, expiry: Some(_) }) => Ok(cost),
+            _ => Err("Token cannot be renewed".to_string()),
+        });
+
+        let cost = match cost {
+            Ok(cost) => cost,
+            Err(err) => {
+                if let Some(digest) = signature_digest {
+                    // Release the signature, so the renewal can be retried
+                    self.used_renewal_signatures.remove(&digest);
+                }
+                log!("Renewal failed: {}", err);
+                let deposit = env::attached_deposit();
+                if deposit > 0 {
+                    Promise::new(payer).transfer(deposit);
+                }
+                return None;
+            }
+        };
+
+        let mut status = status.expect("Token not found");
+        let old_expiry = status.expiry;
+        let now = block_timestamp() / u64::pow(10, 9);
+        let new_expiry = old_expiry.unwrap_or(now).max(now) + seconds as u64;
+        status.expiry = Some(new_expiry);
+        self.token_statuses.insert(&token_id, &status);
+
+        let refund = env::attached_deposit() - cost;
+        if refund > 0 {
+            Promise::new(payer.clone()).transfer(refund);
+        }
+
+        SubscriptionRenewed {
+            token_id: &token_id,
+            owner_id: &payer,
+            seconds,
+            old_expiry,
+            new_expiry,
+            cost: U128(cost),
+        }.emit();
+
+        Some(new_expiry)
+    }
+
+    /// @dev Returns the cost of the given subscription time with the received price
+    #[private]
+    pub fn resolve_required_mint_cost(
+        &self,
+        seconds: u32,
+        tier: String,
+        #[callback_result] price_data: Result<PriceData, PromiseError>,
+    ) -> U128 {
+        let price_data = price_data.unwrap_or_else(|_| env::panic_str("Failed to get price from the price feed"));
+        let price = self.native_usd_price_feed.parse_price_data(&price_data).unwrap_or_else(|err| env::panic_str(&err));
+        U128(self.get_required_mint_cost_for_seconds_internal(seconds, &tier, price).unwrap_or_else(|err| env::panic_str(&err)))
+    }
+
+    /*****************
+    Public interfaces
+    *****************/
+    pub fn version(&self) -> &str { VERSION }
+
+    /// @notice Get the network of the contract, which is part of the mint authorization digests
+    pub fn get_chain_id(&self) -> String {
+        self.chain_id.clone()
+    }
+
+    /// @notice Get the version of the stored state
+    pub fn storage_version(&self) -> String {
+        self.storage_version.clone()
+    }
+
+    /// @notice Get the URI of the token metadata, formatted with the token URI template.
+    /// Falls back to the `reference` of the token if the template cannot be filled in,
+    /// e.g. because the token has no content ID in `extra` or the base URI is not set
+    /// @return An empty string if the token has no URI
+    pub fn token_uri(&self, token_id: TokenId) -> String {
+        let token_metadata_store = self.tokens.token_metadata_by_id.as_ref().expect("Metadata not supported");
+        let token_metadata: TokenMetadata = token_metadata_store.get(&token_id).expect("Token not found");
+        let base_uri = self.metadata.get()
+            .and_then(|metadata| metadata.base_uri)
+            .map(|base_uri| base_uri.trim_end_matches('/').to_string())
+            .filter(|base_uri| !base_uri.is_empty());
+        let content_id = token_metadata.extra.filter(|extra| !extra.is_empty());
+
+        let uri = content_id.and_then(|content_id| match self.token_uri_template {
+            TokenUriTemplate::BaseUri => base_uri.map(|base_uri| format!("{}/{}.json", base_uri, content_id)),
+            TokenUriTemplate::Ipfs => Some(format!("ipfs://{}", content_id)),
+            TokenUriTemplate::IpfsGateway => base_uri.map(|base_uri| format!("{}/ipfs/{}", base_uri, content_id)),
+            TokenUriTemplate::Arweave => Some(format!("ar://{}", content_id)),
+        });
+        uri.or(token_metadata.reference).unwrap_or_default()
+    }
+
+    /// @notice Get the ID of the token minted as the `index`th token (starting from 0), whether or not it exists
+    pub fn token_id_for_index(&self, index: U128) -> TokenId {
+        format!("{}{}", self.token_id_prefix, index.0)
+    }
+
+    /// @notice Get the number of minted and burned tokens. `total_supply` is always `minted - burned`
+    pub fn get_token_counts(&self) -> TokenCounts {
+        TokenCounts {
+            minted: U128(self.next_token_id),
+            burned: U128(self.burned_tokens),
+            total_supply: U128(self.tokens.owner_by_id.len() as u128),
+        }
+    }
+
+    /// @notice Get the pending update of a token authorized with `authorize_token_update`
+    pub fn get_token_update(&self, token_id: TokenId) -> Option<TokenUpdate> {
+        self.pending_token_updates.get(&token_id)
+    }
+
+    /// @notice Get how many tokens an account can hold
+    pub fn get_token_policy(&self) -> TokenPolicy {
+        self.token_policy
+    }
+
+    /// @notice Get the format of the URI returned by `token_uri`
+    pub fn get_token_uri_template(&self) -> TokenUriTemplate {
+        self.token_uri_template
+    }
+
+    /// Check the validity of a specific token
+    pub fn token_is_valid(&self, token_id: TokenId) -> bool {
+        self.tokens.owner_by_id.get(&token_id).expect("Token not found");
+        self.token_statuses.get(&token_id).unwrap_or_default().is_valid()
+    }
+
+    /// Check the expiry of the token (epoch time in seconds) - None means it never expires
+    pub fn token_expiry(&self, token_id: TokenId) -> Option<u64> {
+        self.tokens.owner_by_id.get(&token_id).expect("Token not found");
+        self.token_statuses.get(&token_id).unwrap_or_default().expiry
+    }
+
+    /// Get the verification tier of a specific token
+    pub fn token_tier(&self, token_id: TokenId) -> String {
+        self.tokens.owner_by_id.get(&token_id).expect("Token not found");
+        self.token_tiers.get(&token_id).unwrap_or(DEFAULT_TIER.to_string())
+    }
+
+    /// Get every revocation and reinstatement of a token, oldest first.
+    /// The history is kept after the token is burned
+    pub fn token_revocation_history(&self, token_id: TokenId) -> Vec<RevocationRecord> {
+        self.revocation_history.get(&token_id).unwrap_or_default()
+    }
+
+    /// Check if an account has any valid tokens
+    pub fn has_valid_token(&self, address: AccountId) -> bool {
+        match self.tokens.tokens_per_owner.as_ref().expect("enumeration extension in use").get(&address) {
+            Some(token_ids) => {
+                for token_id in token_ids.iter() {
+                    let status = self.token_statuses.get(&token_id).unwrap_or_default();
+                    if status.is_valid() {
+                        return true;
+                    }
+                }
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// @notice Check the KYC of an account, for use in cross-contract calls
+    /// @param account_id The account to check
+    /// @param min_tier If set, only tokens with this tier or a higher ranked one are accepted
+    /// @return The first valid token of the account satisfying the tier
+    pub fn kyc_check(&self, account_id: AccountId, min_tier: Option<String>) -> KycCheckResult {
+        let token_ids: Vec<TokenId> = self.tokens.tokens_per_owner.as_ref().expect("enumeration extension in use")
+            .get(&account_id)
+            .map(|token_ids| token_ids.to_vec())
+            .unwrap_or_default();
+
+        let mut results = token_ids.into_iter().map(|token_id| {
+            let status = self.token_statuses.get(&token_id).unwrap_or_default();
+            let tier = self.token_tiers.get(&token_id).unwrap_or(DEFAULT_TIER.to_string());
+            KycCheckResult {
+                valid: status.is_valid() && self.tier_satisfies(&tier, min_tier.as_deref()),
+                tier: Some(tier),
+                expiry: status.expiry,
+                token_id: Some(token_id),
+            }
+        }).collect::<Vec<_>>();
+
+        if results.is_empty() {
+            return KycCheckResult { valid: false, tier: None, expiry: None, token_id: None };
+        }
+        let index = results.iter().position(|result| result.valid).unwrap_or(0);
+        results.swap_remove(index)
+    }
+
+    /// Check if an account has any valid tokens with at least the given tier
+    pub fn has_valid_token_with_tier(&self, address: AccountId, min_tier: String) -> bool {
+        self.kyc_check(address, Some(min_tier)).valid
+    }
+
+    /// @notice Get the KYC status of an account with all of its tokens in one call
+    /// Unlike the per token views, this does not panic for accounts without tokens
+    pub fn kyc_status_of(&self, account_id: AccountId) -> KycStatus {
+        let tokens: Vec<TokenStatusView> = self.tokens.tokens_per_owner.as_ref().expect("enumeration extension in use")
+            .get(&account_id)
+            .map(|token_ids| token_ids.iter().map(|token_id| {
+                let status = self.token_statuses.get(&token_id).unwrap_or_default();
+                self.token_status_view(token_id, account_id.clone(), status)
+            }).collect())
+            .unwrap_or_default();
+
+        let valid_tokens = tokens.iter().filter(|token| token.valid);
+        // A token without expiry outlasts any token with one
+        let best_token = valid_tokens.clone()
+            .max_by_key(|token| (self.tier_rank(&token.tier), token.expiry.unwrap_or(u64::MAX)))
+            .cloned();
+        let latest_expiry = valid_tokens.filter_map(|token| token.expiry).max();
+
+        KycStatus {
+            account_id,
+            valid: best_token.is_some(),
+            tokens,
+            best_token,
+            latest_expiry,
+        }
+    }
+
+    /// @notice Get a tier from the tier registry
+    pub fn get_tier(&self, tier: String) -> Option<TierInfo> {
+        self.tiers.get(&tier)
+    }
+
+    /// @notice List tokens with their status, for finding e.g. revoked or expiring tokens
+    /// Scanning stops after `limit` matching tokens or MAX_TOKEN_STATUS_SCAN scanned tokens, so a page can
+    /// contain less tokens than the limit even if there are more matching tokens. Continue from `next_index` until it is None.
+    /// @param from_index Index to start scanning from, defaults to 0
+    /// @param limit Number of tokens to return, defaults to and is capped at MAX_TOKEN_STATUS_QUERY_LIMIT
+    /// @param filter Criteria the returned tokens must match
+    pub fn tokens_with_status(&self, from_index: Option<U128>, limit: Option<u64>, filter: Option<TokenStatusFilter>) -> TokenStatusPage {
+        let from_index = from_index.map(|index| index.0).unwrap_or(0);
+        let limit = limit.unwrap_or(MAX_TOKEN_STATUS_QUERY_LIMIT).min(MAX_TOKEN_STATUS_QUERY_LIMIT);
+        assert!(limit > 0, "Cannot provide limit of 0");
+        let filter = filter.unwrap_or_default();
+
+        let len = self.token_statuses.len();
+        let scan_end = len.min((from_index as u64).saturating_add(MAX_TOKEN_STATUS_SCAN));
+        let mut index = from_index.min(len as u128) as u64;
+        let mut tokens = vec![];
+        while index < scan_end && (tokens.len() as u64) < limit {
+            let token_id = self.token_statuses.keys_as_vector().get(index).expect("Token status index out of bounds");
+            let status = self.token_statuses.values_as_vector().get(index).expect("Token status index out of bounds");
+            index += 1;
+            // Statuses of burned tokens are removed, but skip any left behind instead of failing the whole page
+            let owner_id = match self.tokens.owner_by_id.get(&token_id) {
+                Some(owner_id) => owner_id,
+                None => continue,
+            };
+            let token = self.token_status_view(token_id, owner_id, status);
+            if KycdaoNTNFT::token_matches(&token, &filter) {
+                tokens.push(token);
+            }
+        }
+
+        TokenStatusPage {
+            tokens,
+            next_index: if index < len { Some(U128(index as u128)) } else { None },
+        }
+    }
+
+    /// @notice Get the subscription cost per year of a tier for a subscription of the given length
+    /// @return The cost in USD, to SUBSCRIPTION_COST_DECIMALS decimal places
+    pub fn get_subscription_cost_per_year_for(&self, tier: String, seconds: u32) -> u32 {
+        self.get_subscription_cost_per_year_for_tier(&tier, seconds)
+    }
+
+    /// @notice Get the prices of all tiers, ordered by rank
+    pub fn get_pricing_table(&self) -> Vec<TierPricing> {
+        self.get_tiers().into_iter()
+            .map(|(tier, info)| TierPricing {
+                subscription_cost_per_year: info.subscription_cost_per_year.unwrap_or(self.subscription_cost_per_year),
+                duration_prices: self.duration_prices.get(&tier).unwrap_or_default(),
+                tier,
+            })
+            .collect()
+    }
+
+    /// @notice Get all tiers of the tier registry, ordered by rank
+    pub fn get_tiers(&self) -> Vec<(String, TierInfo)> {
+        let mut tiers = self.tiers.to_vec();
+        tiers.sort_by_key(|(_, info)| info.rank);
+        tiers
+    }
+
+    /// @dev Returns the amount in NATIVE (yoctoNEAR) which is expected for a given amount of subscription time in seconds
+    /// Resolves with the current price from the price feed, so it cannot be called as a view
+    /// @param seconds The number of seconds of subscription time to calculate the cost for
+    pub fn get_required_mint_cost_for_seconds(&self, seconds: u32) -> Promise {
+        self.get_required_mint_cost(seconds, DEFAULT_TIER.to_string())
+    }
+
+    /// Returns the cost for subscription per year in USD, to SUBSCRIPTION_COST_DECIMALS decimal places
+    pub fn get_subscription_cost_per_year_usd(&self) -> u32 {
+        self.subscription_cost_per_year
+    }
+
+    /// @notice Check if an account has a role
+    pub fn has_role(&self, role: Role, account_id: AccountId) -> bool {
+        self.roles.has_role(role, &account_id)
+    }
+
+    /// @notice Get all accounts which have a role
+    pub fn get_role_members(&self, role: Role) -> Vec<AccountId> {
+        self.roles.members(role)
+    }
+
+    /// @notice Get the public key used to verify the mint and renewal signatures of a minter
+    pub fn get_signer_key(&self, account_id: AccountId) -> Option<PublicKey> {
+        self.signer_keys.get(&account_id)
+    }
+
+    /// @notice Get the price feed address used for native - USD conversions
+    pub fn get_price_feed(&self) -> AccountId {
+        self.native_usd_price_feed.price_feed_address()
+    }
+
+    /// @notice Get the asset ID of NEAR used on the price feed
+    pub fn get_price_feed_asset(&self) -> String {
+        self.native_usd_price_feed.asset_id()
+    }
+
+    /// @notice Get the maximum age of a price in seconds before it is rejected as stale
+    pub fn get_price_max_age(&self) -> u32 {
+        self.native_usd_price_feed.max_age_secs()
+    }
+
+    /// @notice Get the last price received from the price feed
+    pub fn get_latest_price(&self) -> Option<(u32, u8)> {
+        self.native_usd_price_feed.latest_price()
+    }
+
+    /*****************
+    Mint authorizer functions
+    *****************/
+    /// Check if the token is verified or not (revoked)
+    pub fn token_is_verified(&self, token_id: TokenId) -> bool {
+        self.assert_role(Role::Revoker);
+        self.tokens.owner_by_id.get(&token_id).expect("Token not found");
+        self.token_statuses.get(&token_id).unwrap_or_default().verified
+    }
+
+    /// @dev Revoke or reinstate a token, the change is recorded in the revocation history of the token
+    /// @param reason Reason code of the change, defaults to unspecified
+    /// @param note Optional free-text explanation
+    pub fn set_verified_token(
+        &mut self,
+        token_id: TokenId,
+        verified: bool,
+        reason: Option<RevocationReason>,
+        note: Option<String>,
+    ) {
+        self.assert_role(Role::Revoker);
+        let owner_id = self.tokens.owner_by_id.get(&token_id).expect("Token not found");
+        KycdaoNTNFT::assert_valid_note(note.as_deref());
+        self.internal_set_verified(&token_id, &owner_id, verified, reason, note.as_deref());
+    }
+
+    /// Update the expiry of a token - expects epoch time in seconds
+    pub fn update_expiry(&mut self, token_id: TokenId, expiry: Option<u64>) {
+        self.assert_role(Role::Minter);
+        self.tokens.owner_by_id.get(&token_id).expect("Token not found");
+        self.internal_update_expiry(&token_id, expiry);
+    }
+
+    /// @dev Revoke or reinstate many tokens in one transaction, like `set_verified_token`.
+    /// Unknown tokens are reported in the result instead of failing the whole batch
+    /// @param tokens Pairs of token ID and the new verified status, at most MAX_BATCH_SIZE
+    /// @param reason Reason code recorded for every token of the batch
+    /// @param note Note recorded for every token of the batch
+    /// @return The outcome for each item, in the order of `tokens`
+    pub fn batch_set_verified(
+        &mut self,
+        tokens: Vec<(TokenId, bool)>,
+        reason: Option<RevocationReason>,
+        note: Option<String>,
+    ) -> Vec<BatchItemResult> {
+        self.assert_role(Role::Revoker);
+        KycdaoNTNFT::assert_valid_batch_size(tokens.len());
+        KycdaoNTNFT::assert_valid_note(note.as_deref());
+
+        tokens.into_iter().map(|(token_id, verified)| {
+            let status = match self.batch_item_owner(&token_id) {
+                Ok(owner_id) => {
+                    self.internal_set_verified(&token_id, &owner_id, verified, reason, note.as_deref());
+                    BatchItemStatus::Updated
+                },
+                Err(status) => status,
+            };
+            BatchItemResult { token_id, status }
+        }).collect()
+    }
+
+    /// @dev Update the expiry of many tokens in one transaction, like `update_expiry`.
+    /// Unknown tokens are reported in the result instead of failing the whole batch
+    /// @param tokens Pairs of token ID and the new expiry (epoch time in seconds), at most MAX_BATCH_SIZE
+    /// @return The outcome for each item, in the order of `tokens`
+    pub fn batch_update_expiry(&mut self, tokens: Vec<(TokenId, Option<u64>)>) -> Vec<BatchItemResult> {
+        self.assert_role(Role::Minter);
+        KycdaoNTNFT::assert_valid_batch_size(tokens.len());
+
+        tokens.into_iter().map(|(token_id, expiry)| {
+            let status = match self.batch_item_owner(&token_id) {
+                Ok(_) => {
+                    self.internal_update_expiry(&token_id, expiry);
+                    BatchItemStatus::Updated
+                },
+                Err(status) => status,
+            };
+            BatchItemResult { token_id, status }
+        }).collect()
+    }
+
+    /// @dev Burn any token, e.g. one minted in error. The storage deposit paid by the token owner is refunded to them
+    /// @param token_id The token to burn
+    /// @param reason Why the token was burned, included in the burn event
+    pub fn admin_burn(&mut self, token_id: TokenId, reason: String) {
+        self.assert_role(Role::Revoker);
+        self.internal_burn(&token_id, Some(&env::predecessor_account_id()), Some(&reason));
+    }
+
+    /*****************
+    Payment
+    *****************/
+    /// @dev For retrieving payments sent to the contract, can be called by anyone.
+    /// Sends the balance above the storage staking and the reserve to the safe address
+    /// @return The amount sent in yoctoNEAR
+    pub fn send_balance_to_safe(&self) -> U128 {
+        self.assert_not_paused(PausableFeature::Withdrawal);
+        let safe_address = self.safe_address.clone().expect("Safe address is not initialized");
+        self.internal_send_balance(safe_address)
+    }
+
+    /// @notice The balance which can be sent out, above the storage staking and the reserve
+    pub fn get_withdrawable_balance(&self) -> U128 {
+        U128(self.withdrawable_balance())
+    }
+
+    pub fn get_safe_address(&self) -> Option<AccountId> {
+        self.safe_address.clone()
+    }
+
+    pub fn get_balance_reserve(&self) -> U128 {
+        U128(self.balance_reserve)
+    }
+
+    /*****************
+    Pausing
+    *****************/
+    /// @notice Check if a part of the contract is paused
+    pub fn is_paused(&self, feature: PausableFeature) -> bool {
+        self.paused_features.contains(&feature)
+    }
+
+    /// @notice Get the paused parts of the contract
+    pub fn get_paused_features(&self) -> Vec<PausableFeature> {
+        self.paused_features.clone()
+    }
+
+    /// @dev Pause a part of the contract, e.g. minting if a signer key leaks.
+    /// Can be called by the owner and guardians, pausing a paused feature is a no-op
+    pub fn pause(&mut self, feature: PausableFeature) {
+        let sender = env::predecessor_account_id();
+        assert!(
+            self.roles.has_role(Role::Owner, &sender) || self.roles.has_role(Role::Guardian, &sender),
+            "Predecessor must have the owner or guardian role",
+        );
+        if !self.paused_features.contains(&feature) {
+            self.paused_features.push(feature);
+            Paused { feature, actor: &sender }.emit();
+        }
+    }
+
+    /// @dev Unpause a part of the contract, only the owner can unpause
+    pub fn unpause(&mut self, feature: PausableFeature) {
+        self.assert_role(Role::Owner);
+        let count = self.paused_features.len();
+        self.paused_features.retain(|paused| *paused != feature);
+        if self.paused_features.len() < count {
+            Unpaused { feature, actor: &env::predecessor_account_id() }.emit();
+        }
+    }
+
+    /*****************
+    Owner functions
+    *****************/
+    /// @notice Set new base URI for references
+    /// @param base_uri String to prepend references
+    pub fn set_base_uri(&mut self, base_uri: String) {
+        self.assert_role(Role::Owner);
+        let mut metadata = self.metadata.get().expect("Metadata not supported");
+        metadata.base_uri = Some(base_uri);
+        self.metadata.set(&metadata);
+
+        TokenUriUpdated { base_uri: metadata.base_uri.as_deref(), template: self.token_uri_template }.emit();
+    }
+
+    /// @notice Set the format of the URI returned by `token_uri`
+    pub fn set_token_uri_template(&mut self, template: TokenUriTemplate) {
+        self.assert_role(Role::Owner);
+        self.token_uri_template = template;
+
+        let base_uri = self.metadata.get().and_then(|metadata| metadata.base_uri);
+        TokenUriUpdated { base_uri: base_uri.as_deref(), template }.emit();
+    }
+
+    /// @notice Set how many tokens an account can hold. Applies to new mints only, existing tokens are not changed
+    pub fn set_token_policy(&mut self, policy: TokenPolicy) {
+        self.assert_role(Role::Owner);
+        self.token_policy = policy;
+
+        TokenPolicyUpdated { policy }.emit();
+    }
+
+    /// @notice Set the cost of subscription per yer
+    /// @param value u32 the cost of subscription per year in USD
+    pub fn set_subscription_cost(&mut self, value: u32) {
+        self.assert_role(Role::PricingAdmin);
+        self.subscription_cost_per_year = value;
+
+        PriceUpdated { subscription_cost_per_year: value }.emit();
+    }
+
+    /// @notice Set the cost of subscription per year for a tier
+    /// @param tier The tier to set the cost for
+    /// @param value The cost of subscription per year in USD, the default subscription cost is used if not set
+    pub fn set_tier_subscription_cost(&mut self, tier: String, value: Option<u32>) {
+        self.assert_role(Role::PricingAdmin);
+        let mut info = self.tiers.get(&tier).expect("Unknown tier");
+        info.subscription_cost_per_year = value;
+        self.tiers.insert(&tier, &info);
+        self.emit_tier_price_updated(&tier);
+    }
+
+    /// @notice Set the discounted subscription costs for longer subscriptions of a tier
+    /// @param tier The tier to set the costs for
+    /// @param duration_prices Costs per year, for subscriptions of at least `min_seconds`, in increasing order
+    pub fn set_duration_prices(&mut self, tier: String, duration_prices: Vec<DurationPrice>) {
+        self.assert_role(Role::PricingAdmin);
+        self.assert_known_tier(&tier);
+        let ordered = duration_prices.windows(2).all(|pair| pair[0].min_seconds < pair[1].min_seconds);
+        assert!(ordered, "Duration prices must be in increasing order of min_seconds");
+
+        if duration_prices.is_empty() {
+            self.duration_prices.remove(&tier);
+        } else {
+            self.duration_prices.insert(&tier, &duration_prices);
+        }
+        self.emit_tier_price_updated(&tier);
+    }
+
+    /// @notice Grant a role to an account
+    /// @param role The role to grant
+    /// @param account_id The account to grant the role to
+    pub fn grant_role(&mut self, role: Role, account_id: AccountId) {
+        self.assert_role(Role::Owner);
+        if self.roles.grant(role, &account_id) {
+            RoleGranted { role, account_id: &account_id, sender: &env::predecessor_account_id() }.emit();
+        }
+    }
+
+    /// @notice Revoke a role from an account
+    /// @param role The role to revoke
+    /// @param account_id The account to revoke the role from
+    pub fn revoke_role(&mut self, role: Role, account_id: AccountId) {
+        self.assert_role(Role::Owner);
+        assert!(
+            role != Role::Owner || self.roles.count(Role::Owner) > 1 || !self.roles.has_role(role, &account_id),
+            "Cannot revoke the last owner"
+        );
+        assert!(
+            role != Role::Owner || account_id != self.tokens.owner_id,
+            "Cannot revoke the owner role from the contract owner, use set_owner"
+        );
+        if self.roles.revoke(role, &account_id) {
+            RoleRevoked { role, account_id: &account_id, sender: &env::predecessor_account_id() }.emit();
+        }
+        if role == Role::Minter && self.signer_keys.remove(&account_id).is_some() {
+            SignerKeyUpdated { account_id: &account_id, public_key: None, sender: &env::predecessor_account_id() }.emit();
+        }
+    }
+
+    /// @notice Set or remove the public key used to verify the mint and renewal signatures of a minter.
+    /// The key is removed when the minter role is revoked, which invalidates its unused signatures
+    /// @param account_id The minter signing with the key
+    /// @param public_key ed25519 public key, or None to remove the key
+    pub fn set_signer_key(&mut self, account_id: AccountId, public_key: Option<PublicKey>) {
+        self.assert_role(Role::Owner);
+        match &public_key {
+            Some(public_key) => {
+                assert!(self.roles.has_role(Role::Minter, &account_id), "Signer must have the minter role");
+                assert_eq!(public_key.curve_type(), CurveType::ED25519, "Only ed25519 keys are supported");
+                self.signer_keys.insert(&account_id, public_key);
+            },
+            None => {
+                self.signer_keys.remove(&account_id);
+            },
+        }
+
+        SignerKeyUpdated { account_id: &account_id, public_key: public_key.as_ref(), sender: &env::predecessor_account_id() }.emit();
+    }
+
+    /// @notice Add or update a tier in the tier registry
+    /// @dev The subscription cost is set by pricing admins with `set_tier_subscription_cost`,
+    /// so it has to be the current one (unset for new tiers)
+    /// @param tier The ID of the tier, as used in authorizations
+    /// @param info The rank, display name and subscription cost of the tier
+    pub fn set_tier(&mut self, tier: String, info: TierInfo) {
+        self.assert_role(Role::Owner);
+        let current_cost = self.tiers.get(&tier).and_then(|current| current.subscription_cost_per_year);
+        assert!(
+            info.subscription_cost_per_year == current_cost,
+            "The subscription cost of a tier can only be changed with set_tier_subscription_cost"
+        );
+        let rank_used = self.tiers.iter().any(|(other, other_info)| other != tier && other_info.rank == info.rank);
+        assert!(!rank_used, "Tier rank already used");
+        self.tiers.insert(&tier, &info);
+
+        TierUpdated {
+            tier: &tier,
+            rank: info.rank,
+            display_name: &info.display_name,
+            subscription_cost_per_year: info.subscription_cost_per_year,
+            requires_reverification: info.requires_reverification,
+        }.emit();
+    }
+
+    /// @notice Remove a tier from the tier registry, so it cannot be authorized anymore
+    /// @param tier The ID of the tier
+    pub fn remove_tier(&mut self, tier: String) {
+        self.assert_role(Role::Owner);
+        assert!(tier != DEFAULT_TIER, "Cannot remove the default tier");
+        assert!(self.tiers.remove(&tier).is_some(), "Unknown tier");
+        self.duration_prices.remove(&tier);
+
+        TierRemoved { tier: &tier }.emit();
+    }
+
+    /// @notice Deploy new code to the contract and migrate the state to it
+    /// @dev The compiled wasm of the new contract version is read as the raw call input
+    pub fn upgrade(&self) -> Promise {
+        self.assert_role(Role::Owner);
+        let code = env::input().expect("No code to upgrade to");
+        let migrate_gas = env::prepaid_gas().0
+            .checked_sub(env::used_gas().0)
+            .and_then(|gas| gas.checked_sub(GAS_FOR_UPGRADE.0))
+            .map(Gas)
+            .expect("Not enough gas to upgrade and migrate");
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            // Versions with `upgrade` already store the chain ID, so it is not passed again
+            .function_call("migrate".to_string(), b"{}".to_vec(), 0, migrate_gas)
+    }
+
+    /// @dev For retrieving payments sent to the contract, sends the balance above the storage staking and the reserve
+    /// @return The amount sent in yoctoNEAR
+    pub fn send_balance_to(&self, recipient: AccountId) -> U128 {
+        self.assert_role(Role::Owner);
+        self.assert_not_paused(PausableFeature::Withdrawal);
+        self.internal_send_balance(recipient)
+    }
+
+    /// @notice Set the treasury account which `send_balance_to_safe` sends the balance to
+    pub fn set_safe_address(&mut self, address: AccountId) {
+        self.assert_role(Role::Owner);
+        SafeAddressUpdated { address: &address, sender: &env::predecessor_account_id() }.emit();
+        self.safe_address = Some(address);
+    }
+
+    /// @notice Set the balance kept in the contract on top of the storage staking when sending the balance out
+    /// @param reserve Amount in yoctoNEAR
+    pub fn set_balance_reserve(&mut self, reserve: U128) {
+        self.assert_role(Role::Owner);
+        self.balance_reserve = reserve.0;
+
+        BalanceReserveUpdated { reserve, sender: &env::predecessor_account_id() }.emit();
+    }
+
+    /// @notice Set the price feed address used for native - USD conversions
+    /// @param address Address the address of the price feed
+    /// @param asset_id The asset ID of NEAR on the price feed
+    pub fn set_price_feed(&mut self, address: AccountId, asset_id: String) {
+        self.assert_role(Role::PricingAdmin);
+        PriceFeedUpdated { address: &address, asset_id: &asset_id, sender: &env::predecessor_account_id() }.emit();
+        self.native_usd_price_feed = PriceFeed::new(address, asset_id);
+    }
+
+    /// @notice Set the maximum age of a price before it is rejected as stale
+    /// @param seconds Maximum age in seconds
+    pub fn set_price_max_age(&mut self, seconds: u32) {
+        self.assert_role(Role::PricingAdmin);
+        self.native_usd_price_feed.set_max_age_secs(seconds);
+
+        PriceMaxAgeUpdated { max_age_secs: seconds, sender: &env::predecessor_account_id() }.emit();
+    }
+
+    /*****************
+    HELPERS
+    *****************/
+    /// The balance above the amount locked for storage staking and the reserve
+    fn withdrawable_balance(&self) -> Balance {
+        let locked = env::storage_byte_cost() * Balance::from(env::storage_usage());
+        env::account_balance().saturating_sub(locked).saturating_sub(self.balance_reserve)
+    }
+
+    fn internal_send_balance(&self, recipient: AccountId) -> U128 {
+        let amount = self.withdrawable_balance();
+        assert!(amount > 0, "No balance to send");
+        Promise::new(recipient.clone()).transfer(amount);
+
+        BalanceSent { recipient: &recipient, amount: U128(amount) }.emit();
+        U128(amount)
+    }
+
+    fn authorization_digest(&self, auth_code: MintAuthorizationCode, dst: &AccountId, nonce: u64) -> Vec<u8> {
+        let payload = AuthorizationDigestPayload {
+            domain: AUTHORIZATION_DIGEST_DOMAIN.to_string(),
+            chain_id: self.chain_id.clone(),
+            contract: env::current_account_id(),
+            auth_code,
+            dst: dst.clone(),
+            nonce,
+        };
+        keccak256(&payload.try_to_vec().expect("Failed to serialize digest"))
+    }
+
+    /// The digest used before nonces and domain separation were added. It is ambiguous,
+    /// e.g. code 12 for 3alice.near has the same digest as code 123 for alice.near
+    fn legacy_digest(auth_code: MintAuthorizationCode, dst: &AccountId) -> Vec<u8> {
+        let contract_addr = env::current_account_id();
+        keccak256(format!("{}{}{}", auth_code, dst, contract_addr).as_bytes())
+    }
+
+    fn hex_encode(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// Marks signed authorizations as used, signatures are not stored so they have no nonce
+    fn signature_digest(&self, auth_code: MintAuthorizationCode, dst: &AccountId) -> Vec<u8> {
+        self.authorization_digest(auth_code, dst, 0)
+    }
+
+    /// Finds the digest of the pending authorization of a code for an account,
+    /// falling back to the legacy digest for authorizations which were not migrated yet
+    fn pending_digest(&self, auth_code: MintAuthorizationCode, dst: &AccountId) -> Option<Vec<u8>> {
+        let digest = self.authorization_digests.get(&(dst.clone(), auth_code))
+            .unwrap_or_else(|| KycdaoNTNFT::legacy_digest(auth_code, dst));
+        if self.authorized_token_metadata.contains_key(&digest) {
+            Some(digest)
+        } else {
+            None
+        }
+    }
+
+    /// Creates the digest of a new authorization with the next nonce, and adds it to the digest index
+    fn next_authorization_digest(&mut self, auth_code: MintAuthorizationCode, dst: &AccountId) -> Vec<u8> {
+        self.authorization_nonce += 1;
+        let digest = self.authorization_digest(auth_code, dst, self.authorization_nonce);
+        self.authorization_digests.insert(&(dst.clone(), auth_code), &digest);
+        digest
+    }
+
+    /// Chain IDs are `near:` followed by the network name, e.g. `near:testnet`
+    pub(crate) fn assert_valid_chain_id(chain_id: &str) {
+        let network = chain_id.strip_prefix("near:").unwrap_or_default();
+        let valid = !network.is_empty()
+            && network.len() <= MAX_CHAIN_NETWORK_LENGTH
+            && network.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        assert!(valid, "Invalid chain ID");
+    }
+
+    /// Tiers which are not in the registry (anymore) only satisfy themselves
+    fn tier_satisfies(&self, tier: &str, min_tier: Option<&str>) -> bool {
+        let min_tier = match min_tier {
+            Some(min_tier) => min_tier,
+            None => return true,
+        };
+        if tier == min_tier {
+            return true;
+        }
+        match (self.tiers.get(&tier.to_string()), self.tiers.get(&min_tier.to_string())) {
+            (Some(info), Some(min_info)) => info.rank >= min_info.rank,
+            _ => false,
+        }
+    }
+
+    fn assert_valid_note(note: Option<&str>) {
+        if let Some(note) = note {
+            assert!(note.len() <= MAX_REVOCATION_NOTE_LENGTH, "Note is too long");
+        }
+    }
+
+    fn assert_valid_batch_size(size: usize) {
+        assert!(size <= MAX_BATCH_SIZE, "Batch is too large, at most {} items are allowed", MAX_BATCH_SIZE);
+    }
+
+    /// Finds the owner of a token to be updated in a batch, or the reason to not update it
+    fn batch_item_owner(&self, token_id: &TokenId) -> Result<AccountId, BatchItemStatus> {
+        if env::prepaid_gas() - env::used_gas() < GAS_PER_BATCH_ITEM {
+            return Err(BatchItemStatus::Skipped);
+        }
+        self.tokens.owner_by_id.get(token_id).ok_or(BatchItemStatus::TokenNotFound)
+    }
+
+    fn internal_set_verified(
+        &mut self,
+        token_id: &TokenId,
+        owner_id: &AccountId,
+        verified: bool,
+        reason: Option<RevocationReason>,
+        note: Option<&str>,
+    ) {
+        let mut status = self.token_statuses.get(token_id).unwrap_or_default();
+        status.verified = verified;
+        self.token_statuses.insert(token_id, &status);
+
+        let record = RevocationRecord {
+            verified,
+            reason: reason.unwrap_or(RevocationReason::Unspecified),
+            note: note.map(|note| note.to_string()),
+            actor: env::predecessor_account_id(),
+            timestamp: block_timestamp() / u64::pow(10, 9),
+        };
+        let mut history = self.revocation_history.get(token_id).unwrap_or_default();
+        history.push(record.clone());
+        self.revocation_history.insert(token_id, &history);
+
+        if verified {
+            TokenReinstated { token_id, owner_id, reason: record.reason, note, actor: &record.actor }.emit();
+        } else {
+            TokenRevoked { token_id, owner_id, reason: record.reason, note, actor: &record.actor }.emit();
+        }
+    }
+
+    fn internal_update_expiry(&mut self, token_id: &TokenId, expiry: Option<u64>) {
+        let mut status = self.token_statuses.get(token_id).unwrap_or_default();
+        let old_expiry = status.expiry;
+        status.expiry = expiry;
+        self.token_statuses.insert(token_id, &status);
+
+        ExpiryUpdated { token_id, old_expiry, new_expiry: expiry }.emit();
+    }
+
+    fn token_status_view(&self, token_id: TokenId, owner_id: AccountId, status: Status) -> TokenStatusView {
+        let tier = self.token_tiers.get(&token_id).unwrap_or(DEFAULT_TIER.to_string());
+        TokenStatusView {
+            valid: status.is_valid(),
+            token_id,
+            owner_id,
+            tier,
+            expiry: status.expiry,
+            verified: status.verified,
+        }
+    }
+
+    /// Rank of a tier, unregistered tiers rank below all registered ones
+    fn tier_rank(&self, tier: &str) -> u32 {
+        self.tiers.get(&tier.to_string()).map_or(0, |info| info.rank)
+    }
+
+    fn token_matches(token: &TokenStatusView, filter: &TokenStatusFilter) -> bool {
+        let expired = match token.expiry {
+            Some(expiry) => expiry <= block_timestamp() / u64::pow(10, 9),
+            None => false,
+        };
+        filter.verified.map_or(true, |verified| token.verified == verified)
+            && filter.expired.map_or(true, |filter_expired| expired == filter_expired)
+            && filter.expiring_before.map_or(true, |ts| token.expiry.map_or(false, |expiry| expiry < ts))
+            && filter.tier.as_ref().map_or(true, |tier| &token.tier == tier)
+    }
+
+    /// Only the owner can renew a token, if it is verified and has an expiry
+    fn assert_renewable(&self, token_id: &TokenId) {
+        let owner_id = self.tokens.owner_by_id.get(token_id).expect("Token not found");
+        assert_eq!(owner_id, env::predecessor_account_id(), "Only the token owner can renew the subscription");
+        let status = self.token_statuses.get(token_id).unwrap_or_default();
+        assert!(status.verified, "Token is revoked");
+        assert!(status.expiry.is_some(), "Token does not expire");
+    }
+
+    fn internal_renew_subscription(&mut self, token_id: TokenId, seconds: u32, signature_digest: Option<Vec<u8>>) -> Promise {
+        assert!(seconds > 0, "Must renew for at least one second");
+        self.native_usd_price_feed.get_price_data().then(
+            Self::ext(env::current_account_id())
+                .with_attached_deposit(env::attached_deposit())
+                .with_static_gas(GAS_FOR_RESOLVE_RENEWAL)
+                .resolve_renew_subscription(token_id, seconds, env::predecessor_account_id(), signature_digest)
+        )
+    }
+
+    fn assert_known_tier(&self, tier: &String) {
+        assert!(self.tiers.get(tier).is_some(), "Unknown tier");
+    }
+
+    fn initial_tiers() -> UnorderedMap<String, TierInfo> {
+        let mut tiers = UnorderedMap::new(StorageKey::Tiers);
+        tiers.insert(&DEFAULT_TIER.to_string(), &TierInfo {
+            rank: 1,
+            display_name: "KYC Tier 1".to_string(),
+            subscription_cost_per_year: None,
+            requires_reverification: false,
+        });
+        tiers
+    }
+
+    /// Returns the cost for subscription per year of a tier in USD, to SUBSCRIPTION_COST_DECIMALS decimal places
+    /// Returns the cost of the longest duration price the subscription qualifies for,
+    /// or the price of the tier if there are none
+    fn get_subscription_cost_per_year_for_tier(&self, tier: &String, seconds: u32) -> u32 {
+        let duration_price = self.duration_prices.get(tier)
+            .and_then(|prices| prices.into_iter().rev().find(|price| price.min_seconds <= seconds));
+        match duration_price {
+            Some(duration_price) => duration_price.subscription_cost_per_year,
+            None => self.tiers.get(tier)
+                .and_then(|info| info.subscription_cost_per_year)
+                .unwrap_or(self.subscription_cost_per_year),
+        }
+    }
+
+    fn emit_tier_price_updated(&self, tier: &String) {
+        TierPriceUpdated {
+            tier,
+            subscription_cost_per_year: self.tiers.get(tier).and_then(|info| info.subscription_cost_per_year),
+            duration_prices: &self.duration_prices.get(tier).unwrap_or_default(),
+        }.emit();
+    }
+
+    fn get_required_mint_cost(&self, seconds: u32, tier: String) -> Promise {
+        self.native_usd_price_feed.get_price_data().then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_RESOLVE_COST)
+                .resolve_required_mint_cost(seconds, tier)
+        )
+    }
+
+    fn assert_role(&self, role: Role) {
+        assert!(self.roles.has_role(role, &env::predecessor_account_id()), "Predecessor must have the {} role", role);
+    }
+
+    /// The deployer gets every role, so the contract is usable without further setup
+    fn initial_roles(owner: &AccountId) -> Roles {
+        let mut roles = Roles::new(owner);
+        for role in [Role::Minter, Role::Revoker, Role::PricingAdmin] {
+            roles.grant(role, owner);
+        }
+        roles
+    }
+
+    /// The message signed for a payload, see `SignedPayload`
+    fn signed_message<T: SignedPayload>(chain_id: &str, payload: &T) -> Vec<u8> {
+        let mut message = T::DOMAIN.try_to_vec().expect("Failed to serialize domain");
+        message.extend(chain_id.try_to_vec().expect("Failed to serialize chain ID"));
+        message.extend(payload.try_to_vec().expect("Failed to serialize payload"));
+        keccak256(&message)
+    }
+
+    /// Signatures are only valid while the signer is a minter
+    fn assert_valid_signer_signature<T: SignedPayload>(&self, signer: &AccountId, payload: &T, signature: &[u8]) {
+        assert!(self.roles.has_role(Role::Minter, signer), "Signer must have the minter role");
+        let public_key = self.signer_keys.get(signer).expect("Signer key not set");
+        // Skip the curve type prefix of the key
+        let public_key = ed25519_dalek::PublicKey::from_bytes(&public_key.as_bytes()[1..]).expect("Invalid signer key");
+        let signature = ed25519_dalek::Signature::try_from(signature).expect("Invalid signature");
+        let message = KycdaoNTNFT::signed_message(&self.chain_id, payload);
+        assert!(public_key.verify(&message, &signature).is_ok(), "Invalid signature");
+    }
+
+    /// Mints the token and stores its status and tier, replaces the previous tokens of the account
+    /// depending on the token policy, then refunds the deposit above the cost and the used storage.
+    /// If the deposit does not cover both, the mint is reverted and the whole deposit is refunded
+    fn internal_mint_kyc_token(
+        &mut self,
+        dst: AccountId,
+        metadata: TokenMetadata,
+        status: Status,
+        tier: String,
+        cost: Balance,
+    ) -> Option<Token> {
+        // Another token might have been minted to the account while waiting for the price
+        if let Err(err) = self.check_token_policy_allows_mint(&dst) {
+            return self.refund_failed_mint(dst, err);
+        }
+
+        let initial_storage_usage = env::storage_usage();
+
+        // The index is only used if the mint succeeds, so indices have no gaps
+        let token_id = self.next_token_id;
+        self.next_token_id = self.next_token_id.checked_add(1).expect("Token ID overflow");
+        let token_id_str = self.token_id_for_index(U128(token_id));
+
+        self.internal_insert_token(&token_id_str, &dst, &metadata);
+        self.token_statuses.insert(&token_id_str, &status);
+        self.token_tiers.insert(&token_id_str, &tier);
+        // The deposit is set once the storage is known, the record has the same size either way
+        self.token_storage_deposits.insert(&token_id_str, &0);
+
+        // Revoking the previous tokens adds to their revocation history, so an upper bound of that storage
+        // has to be covered before anything is revoked. Burning frees storage instead
+        let token_storage = env::storage_usage() - initial_storage_usage;
+        let reserved_storage = self.replacement_storage_bound(&dst, &token_id_str);
+        let required_deposit = cost + env::storage_byte_cost() * Balance::from(token_storage + reserved_storage);
+        let deposit = env::attached_deposit();
+        if deposit < required_deposit {
+            self.internal_remove_token(&token_id_str);
+            self.next_token_id = token_id;
+            return self.refund_failed_mint(dst, &format!("Must attach {} yoctoNEAR to cover the subscription and storage", required_deposit));
+        }
+
+        let token_storage_cost = env::storage_byte_cost() * Balance::from(token_storage);
+        self.token_storage_deposits.insert(&token_id_str, &token_storage_cost);
+        NftMint { owner_id: &dst, token_ids: &[&token_id_str], memo: None }.emit();
+
+        let storage_before_replacement = env::storage_usage();
+        self.replace_previous_tokens(&dst, &token_id_str);
+        let replacement_storage = env::storage_usage().saturating_sub(storage_before_replacement);
+
+        let storage_cost = token_storage_cost + env::storage_byte_cost() * Balance::from(replacement_storage);
+        let refund = deposit - cost - storage_cost;
+        if refund > 0 {
+            Promise::new(dst.clone()).transfer(refund);
+        }
+
+        self.ntnft_token(token_id_str)
+    }
+
+    fn assert_not_paused(&self, feature: PausableFeature) {
+        assert!(!self.paused_features.contains(&feature), "{:?} is paused", feature);
+    }
+
+    /// Policies replacing the previous tokens go through all tokens of the account when minting,
+    /// so accounts with more than MAX_BATCH_SIZE tokens are rejected before paying for the mint.
+    /// Checked again when minting, as other tokens might have been minted while waiting for the price
+    fn assert_token_policy_allows_mint(&self, dst: &AccountId) {
+        if let Err(err) = self.check_token_policy_allows_mint(dst) {
+            env::panic_str(err);
+        }
+    }
+
+    fn check_token_policy_allows_mint(&self, dst: &AccountId) -> Result<(), &'static str> {
+        match self.token_policy {
+            TokenPolicy::RejectIfValid => {
+                if self.has_valid_token(dst.clone()) {
+                    return Err("Account already has a valid token");
+                }
+            },
+            TokenPolicy::RevokePrevious | TokenPolicy::BurnPrevious => {
+                if self.owned_token_ids(dst).len() > MAX_BATCH_SIZE {
+                    return Err("Account has too many tokens to replace, burn some first");
+                }
+            },
+            TokenPolicy::Multiple => {},
+        }
+        Ok(())
+    }
+
+    fn owned_token_ids(&self, owner_id: &AccountId) -> Vec<TokenId> {
+        self.tokens.tokens_per_owner.as_ref().expect("enumeration extension in use")
+            .get(owner_id)
+            .map(|token_ids| token_ids.to_vec())
+            .unwrap_or_default()
+    }
+
+    /// The tokens of `owner_id` other than `new_token_id` which are replaced by it, depending on the token policy
+    fn tokens_to_replace(&self, owner_id: &AccountId, new_token_id: &TokenId) -> Vec<TokenId> {
+        let token_ids = self.owned_token_ids(owner_id).into_iter()
+            .filter(|token_id| token_id != new_token_id);
+        match self.token_policy {
+            TokenPolicy::BurnPrevious => token_ids.collect(),
+            TokenPolicy::RevokePrevious => token_ids
+                .filter(|token_id| self.token_statuses.get(token_id).unwrap_or_default().verified)
+                .collect(),
+            TokenPolicy::RejectIfValid | TokenPolicy::Multiple => vec![],
+        }
+    }
+
+    /// Upper bound of the storage added by `replace_previous_tokens`, only revocations add storage
+    fn replacement_storage_bound(&self, owner_id: &AccountId, new_token_id: &TokenId) -> StorageUsage {
+        if self.token_policy != TokenPolicy::RevokePrevious {
+            return 0;
+        }
+        self.tokens_to_replace(owner_id, new_token_id).len() as StorageUsage * MAX_REPLACEMENT_RECORD_STORAGE
+    }
+
+    /// Revokes or burns the tokens of `owner_id` other than `new_token_id`, depending on the token policy
+    fn replace_previous_tokens(&mut self, owner_id: &AccountId, new_token_id: &TokenId) {
+        let memo = format!("Replaced by token {}", new_token_id);
+        for token_id in self.tokens_to_replace(owner_id, new_token_id) {
+            if self.token_policy == TokenPolicy::BurnPrevious {
+                self.internal_burn(&token_id, None, Some(&memo));
+            } else {
+                self.internal_set_verified(&token_id, owner_id, false, Some(RevocationReason::Superseded), Some(&memo));
+            }
+        }
+    }
+
+    /// Checks if the deadline of a pending authorization has passed
+    fn authorization_expired(&self, digest: &Vec<u8>) -> bool {
+        match self.authorized_deadlines.get(digest) {
+            // Compared in seconds, as any deadline in nanoseconds past u64::MAX / 10^9 would overflow
+            Some(deadline) => deadline <= block_timestamp() / u64::pow(10, 9),
+            None => false,
+        }
+    }
+
+    /// A code can be authorized if it has no pending authorization, an expired authorization can be replaced
+    fn can_authorize(&self, auth_code: MintAuthorizationCode, dst: &AccountId) -> bool {
+        match self.pending_digest(auth_code, dst) {
+            Some(digest) => self.authorization_expired(&digest),
+            None => true,
+        }
+    }
+
+    /// Stores the authorization and records its storage cost as the deposit of the predecessor.
+    /// An expired authorization of the same code and account is removed first, refunding its deposit
+    /// @return The storage cost of the authorization
+    fn internal_authorize_mint(&mut self, authorization: &MintAuthorization) -> Balance {
+        if let Some(expired) = self.pending_digest(authorization.auth_code, &authorization.dst) {
+            self.remove_authorization(&expired);
+        }
+        let initial_storage_usage = env::storage_usage();
+
+        let digest = self.next_authorization_digest(authorization.auth_code, &authorization.dst);
+        self.store_authorization(&digest, authorization);
+
+        // The deposit is recorded before measuring, so its own storage is included
+        let mut deposit = AuthorizationDeposit {
+            authorizer: env::predecessor_account_id(),
+            amount: 0,
+            auth_code: authorization.auth_code,
+            dst: authorization.dst.clone(),
+        };
+        self.authorization_deposits.insert(&digest, &deposit);
+        let amount = env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage_usage);
+        deposit.amount = amount;
+        self.authorization_deposits.insert(&digest, &deposit);
+
+        MintAuthorized {
+            auth_code: authorization.auth_code,
+            dst: &authorization.dst,
+            tier: &authorization.verification_tier,
+            expiry: authorization.expiry,
+            seconds_to_pay: authorization.seconds_to_pay,
+            valid_until: authorization.valid_until,
+        }.emit();
+
+        amount
+    }
+
+    fn store_authorization(&mut self, digest: &Vec<u8>, authorization: &MintAuthorization) {
+        let new_status = Status {
+            verified: true,
+            expiry: authorization.expiry,
+        };
+
+        self.authorized_token_metadata.insert(digest, &authorization.metadata);
+        self.authorized_statuses.insert(digest, &new_status);
+        self.authorized_seconds_to_pay.insert(digest, &authorization.seconds_to_pay);
+        self.authorized_tiers.insert(digest, &authorization.verification_tier);
+        match authorization.valid_until {
+            Some(deadline) => self.authorized_deadlines.insert(digest, &deadline),
+            None => self.authorized_deadlines.remove(digest),
+        };
+    }
+
+    /// Checks that the attached deposit covers the storage cost of new authorizations, and refunds the rest
+    fn charge_authorization_deposit(storage_cost: Balance) {
+        let deposit = env::attached_deposit();
+        assert!(
+            deposit >= storage_cost,
+            "Attached deposit must cover the storage of the authorizations, {} yoctoNEAR required",
+            storage_cost,
+        );
+        if deposit > storage_cost {
+            Promise::new(env::predecessor_account_id()).transfer(deposit - storage_cost);
+        }
+    }
+
+    /// Removes a pending authorization and refunds its storage deposit to the authorizer.
+    /// Authorizations made before storage deposits were required have no deposit to refund
+    fn remove_authorization(&mut self, digest: &Vec<u8>) {
+        self.authorized_token_metadata.remove(digest);
+        self.authorized_statuses.remove(digest);
+        self.authorized_seconds_to_pay.remove(digest);
+        self.authorized_tiers.remove(digest);
+        self.authorized_deadlines.remove(digest);
+        if let Some(deposit) = self.authorization_deposits.remove(digest) {
+            let key = (deposit.dst, deposit.auth_code);
+            if self.authorization_digests.get(&key).as_ref() == Some(digest) {
+                self.authorization_digests.remove(&key);
+            }
+            if deposit.amount > 0 {
+                Promise::new(deposit.authorizer).transfer(deposit.amount);
+            }
+        }
+    }
+
+    /// Removes the pending update of a token and refunds its storage deposit to the authorizer
+    fn internal_remove_token_update(&mut self, token_id: &TokenId) -> Option<TokenUpdate> {
+        let update = self.pending_token_updates.remove(token_id)?;
+        if update.deposit.0 > 0 {
+            Promise::new(update.authorizer.clone()).transfer(update.deposit.0);
+        }
+        Some(update)
+    }
+
+    /// Removes the token and refunds the freed storage to the owner, who paid for it when minting
+    fn internal_burn(&mut self, token_id: &TokenId, authorized_id: Option<&AccountId>, memo: Option<&str>) {
+        let owner_id = self.tokens.owner_by_id.get(token_id).expect("Token not found");
+        // The storage of a pending update was paid by its authorizer
+        self.internal_remove_token_update(token_id);
+        let storage_deposit = self.token_storage_deposits.get(token_id).unwrap_or(0);
+        let initial_storage_usage = env::storage_usage();
+        self.internal_remove_token(token_id);
+        self.burned_tokens += 1;
+
+        // Only the storage paid by the owner is refunded, at most what was freed
+        let freed_storage_cost = env::storage_byte_cost() * Balance::from(initial_storage_usage - env::storage_usage());
+        let refund = storage_deposit.min(freed_storage_cost);
+        if refund > 0 {
+            Promise::new(owner_id.clone()).transfer(refund);
+        }
+
+        NftBurn { owner_id: &owner_id, token_ids: &[token_id], authorized_id, memo }.emit();
+    }
+
+    /// Adds the token to all per-token maps, including enumeration. Unlike `NTNFT::internal_mint`, this
+    /// does not refund the deposit or log `nft_mint`, both are done by `internal_mint_kyc_token`
+    fn internal_insert_token(&mut self, token_id: &TokenId, owner_id: &AccountId, metadata: &TokenMetadata) {
+        assert!(self.tokens.owner_by_id.insert(token_id, owner_id).is_none(), "Token ID must be unique");
+        if let Some(token_metadata_by_id) = &mut self.tokens.token_metadata_by_id {
+            token_metadata_by_id.insert(token_id, metadata);
+        }
+        if let Some(tokens_per_owner) = &mut self.tokens.tokens_per_owner {
+            let mut token_ids = tokens_per_owner.get(owner_id).unwrap_or_else(|| {
+                UnorderedSet::new(StorageKey::TokensPerOwner { account_hash: env::sha256(owner_id.as_bytes()) })
+            });
+            token_ids.insert(token_id);
+            tokens_per_owner.insert(owner_id, &token_ids);
+        }
+    }
+
+    fn internal_remove_token(&mut self, token_id: &TokenId) {
+        let owner_id = self.tokens.owner_by_id.remove(token_id).expect("Token not found");
+        if let Some(token_metadata_by_id) = &mut self.tokens.token_metadata_by_id {
+            token_metadata_by_id.remove(token_id);
+        }
+        if let Some(tokens_per_owner) = &mut self.tokens.tokens_per_owner {
+            let mut token_ids = tokens_per_owner.get(&owner_id).expect("Token not found");
+            token_ids.remove(token_id);
+            if token_ids.is_empty() {
+                tokens_per_owner.remove(&owner_id);
+            } else {
+                tokens_per_owner.insert(&owner_id, &token_ids);
+            }
+        }
+        self.token_statuses.remove(token_id);
+        self.token_tiers.remove(token_id);
+        self.token_storage_deposits.remove(token_id);
+    }
+
+    /// Returns the amount in NATIVE (yoctoNEAR) which is expected when minting per year of subscription.
+    /// Fails instead of overflowing, so the caller can refund the deposit
+    fn get_subscription_price_per_year_native(&self, tier: &String, seconds: u32, (price, decimals): (u32, u8)) -> Result<Balance, String> {
+        let subscription_cost_per_year = self.get_subscription_cost_per_year_for_tier(tier, seconds);
+        u128::checked_pow(10, (YOCTONEAR_TO_NATIVE_DECIMALS - SUBSCRIPTION_COST_DECIMALS + decimals) as u32)
+            .and_then(|decimal_convert| (subscription_cost_per_year as u128).checked_mul(decimal_convert))
+            .map(|cost| cost / price as u128)
+            .ok_or_else(|| "Subscription cost out of range".to_string())
+    }
+
+    fn get_required_mint_cost_for_seconds_internal(&self, seconds: u32, tier: &String, price: (u32, u8)) -> Result<Balance, String> {
+        self.get_subscription_price_per_year_native(tier, seconds, price)?
+            .checked_mul(seconds as u128)
+            .map(|cost| cost / SECS_IN_YEAR)
+            .ok_or_else(|| "Subscription cost out of range".to_string())
+    }
+
+    /// Validates the price received from the price feed and stores it as the latest price
+    fn resolve_price(&mut self, price_data: Result<PriceData, PromiseError>) -> Result<(u32, u8), String> {
+        let price_data = price_data.map_err(|_| "Failed to get price from the price feed".to_string())?;
+        let price = self.native_usd_price_feed.parse_price_data(&price_data)?;
+        self.native_usd_price_feed.set_latest_price(price);
+        Ok(price)
+    }
+
+    /// Refunds the attached deposit of a token update which could not be completed
+    fn refund_failed_update(&self, payer: AccountId, reason: &str) -> Option<Token> {
+        log!("Token update failed: {}", reason);
+        let deposit = env::attached_deposit();
+        if deposit > 0 {
+            Promise::new(payer).transfer(deposit);
+        }
+        None
+    }
+
+    /// Refunds the attached deposit of a mint which could not be completed
+    fn refund_failed_mint(&self, dst: AccountId, reason: &str) -> Option<Token> {
+        log!("Minting failed: {}", reason);
+        let deposit = env::attached_deposit();
+        if deposit > 0 {
+            Promise::new(dst).transfer(deposit);
+        }
+        None
+    }
+}
+
+near_contract_standards::impl_ntnft_core!(KycdaoNTNFT, tokens);
+near_contract_standards::impl_ntnft_enumeration!(KycdaoNTNFT, tokens);
+
+impl Ownable for KycdaoNTNFT {
+    fn get_owner(&self) -> AccountId {
+        self.tokens.owner_id.clone()
+    }
+
+    /// Transfers the owner role too, so `get_owner` is always a member of it
+    fn set_owner(&mut self, owner: AccountId) {
+        self.assert_role(Role::Owner);
+        let sender = env::predecessor_account_id();
+        let previous_owner = std::mem::replace(&mut self.tokens.owner_id, owner.clone());
+        if self.roles.grant(Role::Owner, &owner) {
+            RoleGranted { role: Role::Owner, account_id: &owner, sender: &sender }.emit();
+        }
+        if previous_owner != owner && self.roles.revoke(Role::Owner, &previous_owner) {
+            RoleRevoked { role: Role::Owner, account_id: &previous_owner, sender: &sender }.emit();
+        }
+    }
+}
+
+#[near_bindgen]
+impl NTNFTMetadataProvider for KycdaoNTNFT {
+    fn ntnft_metadata(&self) -> NTNFTContractMetadata {
+        self.metadata.get().unwrap()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
     use near_sdk::{testing_env};
-    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::test_utils::{accounts, get_created_receipts, get_logs, VMContextBuilder};
+    use near_sdk::mock::VmAction;
+    use near_sdk::json_types::U64;
+    use crate::price_feed::{AssetOptionalPrice, Price, TESTNET_PRICE_FEED_ASSET};
+
+    const MINT_STORAGE_COST: u128 = 12000000000000000000000;
+    //const MINT_COST: u128 = near_sdk::ONE_NEAR;
+    const MINT_COST: u128 = 0;
+    /// Attached by default, covers the storage of a mint authorization
+    const AUTHORIZATION_DEPOSIT: u128 = near_sdk::ONE_NEAR / 10;
+    /// Deadline of the renewal signatures made by `sign_renewal`
+    const SIGNATURE_VALID_UNTIL: u64 = 1700000000;
+    const CHAIN_ID: &str = "near:testnet";
+
+    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(accounts(0))
+            .signer_account_id(predecessor_account_id.clone())
+            .predecessor_account_id(predecessor_account_id)
+            .attached_deposit(AUTHORIZATION_DEPOSIT);
+        builder
+    }
+
+    fn sample_token_metadata(extra: String) -> TokenMetadata {
+        TokenMetadata {
+            title: Some("Olympus Mons".into()),
+            description: Some("The tallest mountain in the charted solar system".into()),
+            media: None,
+            media_hash: None,
+            copies: Some(1u64),
+            issued_at: None,
+            expires_at: None,
+            starts_at: None,
+            updated_at: None,
+            extra: Some(extra),
+            reference: None,
+            reference_hash: None,
+        }
+    }
+
+    fn price_data(multiplier: u128, timestamp: u64) -> PriceData {
+        PriceData {
+            timestamp: U64(timestamp),
+            recency_duration_sec: 90,
+            prices: vec![AssetOptionalPrice {
+                asset_id: TESTNET_PRICE_FEED_ASSET.to_string(),
+                price: Some(Price { multiplier: U128(multiplier), decimals: 28 }),
+            }],
+        }
+    }
+
+    /// The price reported by the mock price oracle
+    fn sample_price_data() -> PriceData {
+        price_data(17370, env::block_timestamp())
+    }
+
+    fn mint_with_code(contract: &mut KycdaoNTNFT, auth_code: MintAuthorizationCode) -> Token {
+        contract.mint_with_code(auth_code);
+        contract.resolve_mint_with_code(auth_code, env::predecessor_account_id(), Ok(sample_price_data())).expect("Minting failed")
+    }
+
+    /// Initializes the contract by bob, who has all roles, with the KYC_2 tier and bob's signer key registered.
+    /// The logs of the setup are dropped
+    fn setup_contract(context: &mut VMContextBuilder) -> KycdaoNTNFT {
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.set_tier("KYC_2".to_string(), sample_tier(2, None));
+        contract.set_signer_key(accounts(1), Some(near_public_key(&signer_keypair())));
+
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract
+    }
+
+    /// Authorizes a mint by bob and mints it by `dst`, the context is left with `dst` as the predecessor
+    fn authorize_and_mint(
+        context: &mut VMContextBuilder,
+        contract: &mut KycdaoNTNFT,
+        auth_code: MintAuthorizationCode,
+        dst: AccountId,
+        metadata: TokenMetadata,
+        expiry: Option<u64>,
+        tier: &str,
+    ) -> Token {
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(AUTHORIZATION_DEPOSIT)
+            .signer_account_id(accounts(1))
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.authorize_mint_with_code(auth_code, dst.clone(), metadata, expiry, 0, tier.to_string(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(dst.clone())
+            .predecessor_account_id(dst)
+            .build());
+        mint_with_code(contract, auth_code)
+    }
+
+    fn transferred_to(account_id: AccountId) -> Balance {
+        get_created_receipts().iter()
+            .filter(|receipt| receipt.receiver_id == account_id)
+            .flat_map(|receipt| receipt.actions.iter())
+            .map(|action| match action {
+                VmAction::Transfer { deposit } => *deposit,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    // TODO fix this - contract state is not deleted between tests
+    #[test]
+    #[ignore]
+    fn test_new() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = KycdaoNTNFT::new_default_meta("base2".to_string(), CHAIN_ID.to_string());
+        testing_env!(context.is_view(true).build());
+        assert_eq!(contract.ntnft_token("1".to_string()), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The contract is not initialized")]
+    fn test_default() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let _contract = KycdaoNTNFT::default();
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        testing_env!(context.is_view(true).build());
+        assert_eq!(contract.ntnft_metadata().base_uri, Some("base".to_string()));
+    }
+
+    #[test]
+    fn test_authorized_minting() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+        contract.authorize_mint_with_code(365, accounts(2), sample_token_metadata("othersomehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
+            .build());
+
+        let token = mint_with_code(&mut contract, 365);
+        assert_eq!(token.token_id, "0".to_string());
+        assert_eq!(token.owner_id, accounts(2));
+        assert_eq!(token.metadata.unwrap(), sample_token_metadata("othersomehash".to_string()));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
+            .build());
+
+        let token = mint_with_code(&mut contract, 123);
+        assert_eq!(token.token_id, "1".to_string());
+        assert_eq!(token.owner_id, accounts(2));
+        assert_eq!(token.metadata.unwrap(), sample_token_metadata("somehash".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized code")]
+    fn test_unauthorized_minting() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
+            .build());
+
+        contract.mint_with_code(123);
+    }
+
+    fn signer_keypair() -> ed25519_dalek::Keypair {
+        let secret = ed25519_dalek::SecretKey::from_bytes(&[7u8; 32]).unwrap();
+        let public = ed25519_dalek::PublicKey::from(&secret);
+        ed25519_dalek::Keypair { secret, public }
+    }
+
+    fn near_public_key(keypair: &ed25519_dalek::Keypair) -> PublicKey {
+        let mut data = vec![CurveType::ED25519 as u8];
+        data.extend_from_slice(keypair.public.as_bytes());
+        PublicKey::try_from(data).unwrap()
+    }
+
+    fn sample_tier(rank: u32, subscription_cost_per_year: Option<u32>) -> TierInfo {
+        TierInfo {
+            rank,
+            display_name: format!("KYC Tier {}", rank),
+            subscription_cost_per_year,
+            requires_reverification: false,
+        }
+    }
+
+    fn sample_mint_payload(auth_code: MintAuthorizationCode, dst: AccountId, tier: String) -> MintSignaturePayload {
+        MintSignaturePayload {
+            auth_code,
+            dst,
+            metadata: sample_token_metadata("somehash".to_string()),
+            expiry: Some(9000000000),
+            seconds_to_pay: 0,
+            tier,
+            contract: accounts(0),
+        }
+    }
+
+    fn sign_mint(keypair: &ed25519_dalek::Keypair, auth_code: MintAuthorizationCode, dst: AccountId, tier: String) -> Vec<u8> {
+        use ed25519_dalek::Signer;
+        let payload = sample_mint_payload(auth_code, dst, tier);
+        let message = KycdaoNTNFT::signed_message(CHAIN_ID, &payload);
+        keypair.sign(&message).to_bytes().to_vec()
+    }
+
+    fn setup_signature_minting(context: &mut VMContextBuilder) -> (KycdaoNTNFT, ed25519_dalek::Keypair) {
+        let contract = setup_contract(context);
+        let keypair = signer_keypair();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
+            .build());
+
+        (contract, keypair)
+    }
+
+    #[test]
+    #[should_panic(expected = "Signer must have the minter role")]
+    fn test_signature_of_revoked_minter() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, keypair) = setup_signature_minting(&mut context);
+        let signature = sign_mint(&keypair, 123, accounts(2), DEFAULT_TIER.to_string());
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.grant_role(Role::Owner, accounts(3));
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.revoke_role(Role::Minter, accounts(1));
+        assert!(contract.get_signer_key(accounts(1)).is_none());
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.mint_with_signature(123, sample_token_metadata("somehash".to_string()), Some(9000000000), 0, DEFAULT_TIER.to_string(), accounts(1), signature);
+    }
+
+    #[test]
+    #[should_panic(expected = "Signer key not set")]
+    fn test_signature_of_minter_without_key() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, keypair) = setup_signature_minting(&mut context);
+        let signature = sign_mint(&keypair, 123, accounts(2), DEFAULT_TIER.to_string());
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.grant_role(Role::Minter, accounts(3));
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.mint_with_signature(123, sample_token_metadata("somehash".to_string()), Some(9000000000), 0, DEFAULT_TIER.to_string(), accounts(3), signature);
+    }
+
+    #[test]
+    #[should_panic(expected = "Signer must have the minter role")]
+    fn test_set_signer_key_of_non_minter() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        contract.set_signer_key(accounts(2), Some(near_public_key(&signer_keypair())));
+    }
+
+    #[test]
+    fn test_signature_minting() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, keypair) = setup_signature_minting(&mut context);
+
+        let signature = sign_mint(&keypair, 123, accounts(2), DEFAULT_TIER.to_string());
+        contract.mint_with_signature(123, sample_token_metadata("somehash".to_string()), Some(9000000000), 0, DEFAULT_TIER.to_string(), accounts(1), signature);
+        let payload = sample_mint_payload(123, accounts(2), DEFAULT_TIER.to_string());
+        let token = contract.resolve_mint_with_signature(payload, Ok(sample_price_data())).expect("Minting failed");
+
+        assert_eq!(token.token_id, "0".to_string());
+        assert_eq!(token.owner_id, accounts(2));
+        assert_eq!(token.metadata.unwrap(), sample_token_metadata("somehash".to_string()));
+        assert_eq!(contract.token_expiry(token.token_id.clone()), Some(9000000000));
+        assert_eq!(contract.token_tier(token.token_id.clone()), DEFAULT_TIER.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid signature")]
+    fn test_tampered_signature_minting() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, keypair) = setup_signature_minting(&mut context);
+
+        let signature = sign_mint(&keypair, 123, accounts(2), DEFAULT_TIER.to_string());
+        contract.mint_with_signature(123, sample_token_metadata("somehash".to_string()), Some(9000000000), 0, "KYC_2".to_string(), accounts(1), signature);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid signature")]
+    fn test_signature_minting_for_other_account() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, keypair) = setup_signature_minting(&mut context);
+
+        let signature = sign_mint(&keypair, 123, accounts(3), DEFAULT_TIER.to_string());
+        contract.mint_with_signature(123, sample_token_metadata("somehash".to_string()), Some(9000000000), 0, DEFAULT_TIER.to_string(), accounts(1), signature);
+    }
+
+    #[test]
+    #[should_panic(expected = "Signature already used")]
+    fn test_replayed_signature_minting() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, keypair) = setup_signature_minting(&mut context);
+
+        let signature = sign_mint(&keypair, 123, accounts(2), DEFAULT_TIER.to_string());
+        contract.mint_with_signature(123, sample_token_metadata("somehash".to_string()), Some(9000000000), 0, DEFAULT_TIER.to_string(), accounts(1), signature.clone());
+        contract.mint_with_signature(123, sample_token_metadata("somehash".to_string()), Some(9000000000), 0, DEFAULT_TIER.to_string(), accounts(1), signature);
+    }
+
+    #[test]
+    fn test_signature_released_on_failed_mint() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, keypair) = setup_signature_minting(&mut context);
+
+        let signature = sign_mint(&keypair, 123, accounts(2), DEFAULT_TIER.to_string());
+        contract.mint_with_signature(123, sample_token_metadata("somehash".to_string()), Some(9000000000), 0, DEFAULT_TIER.to_string(), accounts(1), signature.clone());
+        let payload = sample_mint_payload(123, accounts(2), DEFAULT_TIER.to_string());
+        assert!(contract.resolve_mint_with_signature(payload, Err(PromiseError::Failed)).is_none());
+        assert_eq!(transferred_to(accounts(2)), MINT_STORAGE_COST + MINT_COST);
+
+        // the same signature can be used again
+        contract.mint_with_signature(123, sample_token_metadata("somehash".to_string()), Some(9000000000), 0, DEFAULT_TIER.to_string(), accounts(1), signature);
+    }
+
+    /// Authorizes a paid mint for a year, and calls `mint_with_code` with the deposit
+    fn setup_paid_minting(context: &mut VMContextBuilder, deposit: Balance) -> KycdaoNTNFT {
+        let mut contract = setup_contract(context);
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, SECS_IN_YEAR as u32, DEFAULT_TIER.to_string(), None);
+
+        testing_env!(context
+            .block_timestamp(1664226405000000000)
+            .storage_usage(env::storage_usage())
+            .attached_deposit(deposit)
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
+            .build());
+
+        contract.mint_with_code(123);
+        contract
+    }
+
+    /// 5 USD at 1.7370 USD / NEAR
+    fn paid_mint_cost() -> Balance {
+        5 * u128::pow(10, 28) / 17370
+    }
+
+    /// Storage cost of the mint in `setup_paid_minting`: 996 bytes at 10^19 yoctoNEAR per byte.
+    /// Each record costs its key and value plus 40 bytes:
+    /// owner_by_id value and tree node (58 + 73), token metadata (146), tokens_per_owner entry and set (144 + 91 + 91),
+    /// token status (55 + 55 + 52), tier (55 + 55 + 59) and storage deposit (62)
+    const PAID_MINT_STORAGE_COST: u128 = 9960000000000000000000;
+
+    #[test]
+    fn test_minting_refunds_excess_deposit() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_paid_minting(&mut context, 10 * near_sdk::ONE_NEAR);
+
+        assert!(contract.resolve_mint_with_code(123, accounts(2), Ok(sample_price_data())).is_some());
+        assert_eq!(transferred_to(accounts(2)), 10 * near_sdk::ONE_NEAR - paid_mint_cost() - PAID_MINT_STORAGE_COST);
+    }
+
+    #[test]
+    fn test_minting_with_exact_deposit() {
+        let mut context = get_context(accounts(1));
+        let deposit = paid_mint_cost() + PAID_MINT_STORAGE_COST;
+        let mut contract = setup_paid_minting(&mut context, deposit);
+
+        assert!(contract.resolve_mint_with_code(123, accounts(2), Ok(sample_price_data())).is_some());
+        assert_eq!(transferred_to(accounts(2)), 0);
+    }
+
+    #[test]
+    fn test_minting_with_insufficient_storage_deposit() {
+        let mut context = get_context(accounts(1));
+        let deposit = paid_mint_cost() + PAID_MINT_STORAGE_COST - 1;
+        let mut contract = setup_paid_minting(&mut context, deposit);
+
+        assert!(contract.resolve_mint_with_code(123, accounts(2), Ok(sample_price_data())).is_none());
+        assert_eq!(transferred_to(accounts(2)), deposit);
+        assert!(contract.tokens.owner_by_id.get(&"0".to_string()).is_none());
+        assert!(!contract.has_valid_token(accounts(2)));
+        assert!(contract.pending_digest(123, &accounts(2)).is_some());
+        assert_eq!(contract.next_token_id, 0);
+    }
+
+    #[test]
+    fn test_paid_minting() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_paid_minting(&mut context, 3 * near_sdk::ONE_NEAR);
+
+        let token = contract.resolve_mint_with_code(123, accounts(2), Ok(sample_price_data()));
+        assert_eq!(token.unwrap().owner_id, accounts(2));
+        assert_eq!(contract.get_latest_price(), Some((17370, 4)));
+    }
+
+    #[test]
+    fn test_insufficient_payment_minting() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_paid_minting(&mut context, near_sdk::ONE_NEAR);
+
+        // 5 USD at 1.7370 USD / NEAR costs more than 1 NEAR
+        assert!(contract.resolve_mint_with_code(123, accounts(2), Ok(sample_price_data())).is_none());
+        assert_eq!(transferred_to(accounts(2)), near_sdk::ONE_NEAR);
+        assert!(contract.pending_digest(123, &accounts(2)).is_some());
+    }
+
+    #[test]
+    fn test_minting_with_stale_price() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_paid_minting(&mut context, 3 * near_sdk::ONE_NEAR);
+
+        let stale_timestamp = env::block_timestamp() - 10 * 60 * u64::pow(10, 9);
+        assert!(contract.resolve_mint_with_code(123, accounts(2), Ok(price_data(17370, stale_timestamp))).is_none());
+        assert_eq!(transferred_to(accounts(2)), 3 * near_sdk::ONE_NEAR);
+        assert_eq!(contract.get_latest_price(), None);
+        assert!(contract.pending_digest(123, &accounts(2)).is_some());
+    }
+
+    #[test]
+    fn test_minting_with_missing_price() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_paid_minting(&mut context, 3 * near_sdk::ONE_NEAR);
+
+        let mut missing_price = sample_price_data();
+        missing_price.prices[0].price = None;
+        assert!(contract.resolve_mint_with_code(123, accounts(2), Ok(missing_price)).is_none());
+        assert_eq!(transferred_to(accounts(2)), 3 * near_sdk::ONE_NEAR);
+    }
+
+    #[test]
+    fn test_minting_with_failed_price_feed() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_paid_minting(&mut context, 3 * near_sdk::ONE_NEAR);
+
+        assert!(contract.resolve_mint_with_code(123, accounts(2), Err(PromiseError::Failed)).is_none());
+        assert_eq!(transferred_to(accounts(2)), 3 * near_sdk::ONE_NEAR);
+    }
+
+    #[test]
+    fn test_minting_with_unsupported_price_decimals() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_paid_minting(&mut context, 3 * near_sdk::ONE_NEAR);
+
+        let mut price = sample_price_data();
+        price.prices[0].price = Some(Price { multiplier: U128(17370), decimals: 37 });
+        assert!(contract.resolve_mint_with_code(123, accounts(2), Ok(price)).is_none());
+        assert_eq!(transferred_to(accounts(2)), 3 * near_sdk::ONE_NEAR);
+        assert_eq!(get_logs().last().unwrap(), "Minting failed: Unsupported price decimals");
+    }
+
+    #[test]
+    fn test_minting_with_cost_out_of_range() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_paid_minting(&mut context, 3 * near_sdk::ONE_NEAR);
+
+        // 5 USD at 10^-12 USD / NEAR for a year does not fit in a u128 of yoctoNEAR
+        let mut price = sample_price_data();
+        price.prices[0].price = Some(Price { multiplier: U128(1), decimals: 36 });
+        assert!(contract.resolve_mint_with_code(123, accounts(2), Ok(price)).is_none());
+        assert_eq!(transferred_to(accounts(2)), 3 * near_sdk::ONE_NEAR);
+        assert_eq!(get_logs().last().unwrap(), "Minting failed: Subscription cost out of range");
+        assert!(contract.pending_digest(123, &accounts(2)).is_some());
+    }
+
+    #[test]
+    fn test_required_mint_cost() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        // 5 USD per year at 1.7370 USD / NEAR
+        let cost = contract.resolve_required_mint_cost(SECS_IN_YEAR as u32, DEFAULT_TIER.to_string(), Ok(sample_price_data()));
+        assert_eq!(cost.0, 5 * u128::pow(10, 28) / 17370);
+    }
+
+    #[test]
+    fn test_authorization_and_mint_events() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), Some(9000000000), 0, DEFAULT_TIER.to_string(), None);
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"mint_authorized","data":[{"auth_code":123,"dst":"charlie","tier":"KYC_1","expiry":9000000000,"seconds_to_pay":0,"valid_until":null}]}"#
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
+            .build());
+
+        mint_with_code(&mut contract, 123);
+        assert_eq!(get_logs(), vec![
+            "Checking minting authorization for charlie with code: 123",
+            r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_mint","data":[{"owner_id":"charlie","token_ids":["0"]}]}"#,
+        ]);
+    }
+
+    #[test]
+    fn test_status_events() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
+            .build());
+
+        let token = mint_with_code(&mut contract, 123);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .signer_account_id(accounts(1))
+            .predecessor_account_id(accounts(1))
+            .build());
+
+        contract.set_verified_token(token.token_id.clone(), false, None, None);
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"token_revoked","data":[{"token_id":"0","owner_id":"charlie","reason":"unspecified","actor":"bob"}]}"#
+        );
+
+        contract.set_verified_token(token.token_id.clone(), true, None, None);
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"token_reinstated","data":[{"token_id":"0","owner_id":"charlie","reason":"unspecified","actor":"bob"}]}"#
+        );
+
+        contract.update_expiry(token.token_id.clone(), Some(9000000000));
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"expiry_updated","data":[{"token_id":"0","old_expiry":null,"new_expiry":9000000000}]}"#
+        );
+
+        contract.set_subscription_cost(10 * u32::pow(10, SUBSCRIPTION_COST_DECIMALS as u32));
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"price_updated","data":[{"subscription_cost_per_year":1000000000}]}"#
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Unauthorized code")]
+    fn test_cancel_mint_authorization() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+        contract.cancel_mint_authorization(123, accounts(2));
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"mint_authorization_cancelled","data":[{"auth_code":123,"dst":"charlie"}]}"#
+        );
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
+            .build());
+
+        contract.mint_with_code(123);
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must have the minter role")]
+    fn test_unauthorized_cancel_mint_authorization() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+
+        testing_env!(context
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
+            .build());
+
+        contract.cancel_mint_authorization(123, accounts(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Authorization expired")]
+    fn test_expired_mint_authorization() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), Some(1664226000));
+
+        testing_env!(context
+            .block_timestamp(1664226405000000000)
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
+            .build());
+
+        contract.mint_with_code(123);
+    }
+
+    #[test]
+    fn test_reauthorize_expired_mint_authorization() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(1664226405000000000).build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), Some(1664226000));
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("otherhash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
+            .build());
+
+        let token = mint_with_code(&mut contract, 123);
+        assert_eq!(token.metadata.unwrap(), sample_token_metadata("otherhash".to_string()));
+    }
+
+    #[test]
+    fn test_purge_expired_authorizations() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(1664226405000000000).build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        contract.authorize_mint_with_code(1, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), Some(1664226000));
+        contract.authorize_mint_with_code(2, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), Some(1664226100));
+        // A deadline which would overflow in nanoseconds
+        contract.authorize_mint_with_code(3, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), Some(u64::MAX));
+        contract.authorize_mint_with_code(4, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+
+        // anyone can purge
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .signer_account_id(accounts(3))
+            .predecessor_account_id(accounts(3))
+            .build());
+
+        // The first expired authorization is replaced by the last one, so the scan stays at index 0
+        assert_eq!(contract.purge_expired_authorizations(None, 1), Some(U128(0)));
+        assert_eq!(get_logs(), vec![
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"mint_authorization_cancelled","data":[{"auth_code":1,"dst":"charlie"}]}"#,
+        ]);
+        assert_eq!(contract.purge_expired_authorizations(Some(U128(0)), 10), None);
+        assert_eq!(contract.purge_expired_authorizations(None, 10), None);
+
+        let is_authorized = |code| contract.pending_digest(code, &accounts(2)).is_some();
+        assert!(!is_authorized(1));
+        assert!(!is_authorized(2));
+        assert!(is_authorized(3));
+        assert!(is_authorized(4));
+        assert_eq!(contract.authorized_statuses.len(), 2);
+        assert_eq!(contract.authorized_deadlines.len(), 1);
+    }
+
+    #[test]
+    fn test_purge_expired_legacy_authorizations() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(1664226405000000000).build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.authorize_mint_with_code(1, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), Some(1664226000));
+        // Authorizations made before v0.5.0 have no deposit record
+        let digest = contract.pending_digest(1, &accounts(2)).unwrap();
+        contract.authorization_deposits.remove(&digest);
+
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        assert_eq!(contract.purge_expired_authorizations(None, 10), None);
+        assert!(contract.pending_digest(1, &accounts(2)).is_none());
+        assert_eq!(get_logs(), vec![format!(
+            r#"EVENT_JSON:{{"standard":"kycdao","version":"1.0.0","event":"legacy_mint_authorization_purged","data":[{{"digest":"{}"}}]}}"#,
+            KycdaoNTNFT::hex_encode(&digest),
+        )]);
+    }
+
+    // TODO fix this - contract state is not deleted between tests
+    #[test]
+    #[ignore]
+    fn test_status_modifications() {
+        let mut context = get_context(accounts(3));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base3".to_string(), CHAIN_ID.to_string());
+
+        // use default status fallback
+        contract.authorize_mint_with_code(489, accounts(3), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+
+        testing_env!(context
+            .block_timestamp(1664226405000000000)
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(3))
+            .predecessor_account_id(accounts(3))
+            .build());
+
+        let token = mint_with_code(&mut contract, 489);
+
+        assert_eq!(contract.token_expiry(token.token_id.clone()), None);
+        assert_eq!(contract.token_statuses.get(&token.token_id).unwrap().verified, true);
+        assert_eq!(contract.token_is_valid(token.token_id.clone()), true);
+        assert_eq!(contract.has_valid_token(accounts(3)), true);
+
+        contract.update_expiry(token.token_id.clone(), Some(1000));
+
+        assert_eq!(contract.token_expiry(token.token_id.clone()), Some(1000));
+        assert_eq!(contract.token_statuses.get(&token.token_id).unwrap().verified, true);
+        assert_eq!(contract.token_is_valid(token.token_id.clone()), false);
+        assert_eq!(contract.has_valid_token(accounts(3)), false);
+
+        contract.update_expiry(token.token_id.clone(), Some(9000000000));
+
+        assert_eq!(contract.token_expiry(token.token_id.clone()), Some(9000000000));
+        assert_eq!(contract.token_statuses.get(&token.token_id).unwrap().verified, true);
+        assert_eq!(contract.token_is_valid(token.token_id.clone()), true);
+        assert_eq!(contract.has_valid_token(accounts(3)), true);
+
+        contract.set_verified_token(token.token_id.clone(), false, None, None);
+
+        assert_eq!(contract.token_expiry(token.token_id.clone()), Some(9000000000));
+        assert_eq!(contract.token_statuses.get(&token.token_id).unwrap().verified, false);
+        assert_eq!(contract.token_is_valid(token.token_id.clone()), false);
+        assert_eq!(contract.has_valid_token(accounts(3)), false);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(AUTHORIZATION_DEPOSIT)
+            .build());
+        contract.authorize_mint_with_code(789, accounts(3), sample_token_metadata("other".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+
+        testing_env!(context
+            .block_timestamp(1664226405000000000)
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(3))
+            .predecessor_account_id(accounts(3))
+            .build());
+
+        let token_new = mint_with_code(&mut contract, 789);
+
+        assert_eq!(contract.token_is_valid(token.token_id.clone()), false);
+        assert_eq!(contract.token_is_valid(token_new.token_id.clone()), true);
+
+        // default is valid
+        assert_eq!(contract.has_valid_token(accounts(3)), true);
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must have the minter role")]
+    fn test_unauthorized_expiry_change() {
+        let mut context = get_context(accounts(3));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base3".to_string(), CHAIN_ID.to_string());
+
+        // use default status fallback
+        contract.authorize_mint_with_code(489, accounts(3), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+
+        testing_env!(context
+            .block_timestamp(1664226405000000000)
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(3))
+            .predecessor_account_id(accounts(3))
+            .build());
+
+        let token = mint_with_code(&mut contract, 489);
+
+        testing_env!(context
+            .block_timestamp(1664226405000000000)
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(4))
+            .predecessor_account_id(accounts(4))
+            .build());
+
+        contract.update_expiry(token.token_id.clone(), Some(1000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must have the revoker role")]
+    fn test_unauthorized_revoke() {
+        let mut context = get_context(accounts(3));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base3".to_string(), CHAIN_ID.to_string());
+
+        // use default status fallback
+        contract.authorize_mint_with_code(489, accounts(3), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+
+        testing_env!(context
+            .block_timestamp(1664226405000000000)
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(3))
+            .predecessor_account_id(accounts(3))
+            .build());
+
+        let token = mint_with_code(&mut contract, 489);
+
+        testing_env!(context
+            .block_timestamp(1664226405000000000)
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(4))
+            .predecessor_account_id(accounts(4))
+            .build());
+
+        contract.set_verified_token(token.token_id.clone(), false, None, None);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_status_setting_on_authorization() {
+        let mut context = get_context(accounts(4));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base3".to_string(), CHAIN_ID.to_string());
+
+        // use default status fallback
+        contract.authorize_mint_with_code(6547, accounts(4), sample_token_metadata("somehash".to_string()), Some(9000000000), 0, DEFAULT_TIER.to_string(), None);
+
+        testing_env!(context
+            .block_timestamp(1664226405000000000)
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(4))
+            .predecessor_account_id(accounts(4))
+            .build());
+
+        let token = mint_with_code(&mut contract, 6547);
+
+        assert_eq!(contract.token_expiry(token.token_id.clone()), Some(9000000000));
+        assert_eq!(contract.token_statuses.get(&token.token_id).unwrap().verified, true);
+        assert_eq!(contract.has_valid_token(accounts(4)), true);
+    }
+
+    /// The context of a call with `input` as the raw call input
+    fn with_input(context: &mut VMContextBuilder, input: Vec<u8>) -> VMContext {
+        let mut vm_context = context.build();
+        vm_context.input = input;
+        vm_context
+    }
+
+    #[test]
+    fn test_upgrade() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        assert_eq!(contract.version(), VERSION);
+        assert_eq!(contract.storage_version(), VERSION);
+
+        // The README sends the wasm file as is, base64 encoded only for the CLI
+        testing_env!(with_input(&mut context, vec![0, 1, 2]));
+
+        contract.upgrade();
+
+        let receipts = get_created_receipts();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].receiver_id, accounts(0));
+        assert!(matches!(&receipts[0].actions[0], VmAction::DeployContract { code } if code == &vec![0, 1, 2]));
+        assert!(matches!(&receipts[0].actions[1], VmAction::FunctionCall { function_name, .. } if function_name == "migrate"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough gas to upgrade and migrate")]
+    fn test_upgrade_without_enough_gas() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        testing_env!(with_input(context.prepaid_gas(GAS_FOR_UPGRADE - Gas(1)), vec![0, 1, 2]));
+        contract.upgrade();
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must have the owner role")]
+    fn test_unauthorized_upgrade() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        testing_env!(with_input(context.predecessor_account_id(accounts(2)), vec![0, 1, 2]));
+        contract.upgrade();
+    }
+
+    #[test]
+    fn test_roles() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        for role in [Role::Owner, Role::Minter, Role::Revoker, Role::PricingAdmin] {
+            assert_eq!(contract.get_role_members(role), vec![accounts(1)]);
+        }
+
+        contract.grant_role(Role::Minter, accounts(2));
+        contract.grant_role(Role::Minter, accounts(3));
+        // Granting a role again is a no-op
+        contract.grant_role(Role::Minter, accounts(3));
+        assert_eq!(contract.get_role_members(Role::Minter), vec![accounts(1), accounts(2), accounts(3)]);
+        assert_eq!(get_logs(), vec![
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"role_granted","data":[{"role":"minter","account_id":"charlie","sender":"bob"}]}"#,
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"role_granted","data":[{"role":"minter","account_id":"danny","sender":"bob"}]}"#,
+        ]);
+
+        // Any minter can authorize mints
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.authorize_mint_with_code(123, accounts(4), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+        assert!(!contract.has_role(Role::Revoker, accounts(3)));
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.revoke_role(Role::Minter, accounts(3));
+        assert!(!contract.has_role(Role::Minter, accounts(3)));
+        assert!(contract.has_role(Role::Minter, accounts(2)));
+        assert_eq!(get_logs(), vec![
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"role_revoked","data":[{"role":"minter","account_id":"danny","sender":"bob"}]}"#,
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must have the minter role")]
+    fn test_revoked_minter() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.grant_role(Role::Minter, accounts(2));
+        contract.revoke_role(Role::Minter, accounts(2));
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.authorize_mint_with_code(123, accounts(4), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must have the pricing_admin role")]
+    fn test_unauthorized_price_change() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.grant_role(Role::Owner, accounts(2));
+
+        // Owners can't set prices without the pricing admin role
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.set_subscription_cost(10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must have the owner role")]
+    fn test_unauthorized_grant_role() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.grant_role(Role::Minter, accounts(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot revoke the last owner")]
+    fn test_revoke_last_owner() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.grant_role(Role::Owner, accounts(2));
+        contract.revoke_role(Role::Owner, accounts(2));
+        contract.revoke_role(Role::Owner, accounts(1));
+    }
+
+    #[test]
+    fn test_set_owner() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.grant_role(Role::Owner, accounts(3));
+
+        testing_env!(context.build());
+        contract.set_owner(accounts(2));
+        assert_eq!(contract.get_owner(), accounts(2));
+        // The last member takes the place of the previous owner in the member set
+        assert_eq!(contract.get_role_members(Role::Owner), vec![accounts(2), accounts(3)]);
+        assert_eq!(get_logs(), vec![
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"role_granted","data":[{"role":"owner","account_id":"charlie","sender":"bob"}]}"#,
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"role_revoked","data":[{"role":"owner","account_id":"bob","sender":"bob"}]}"#,
+        ]);
+
+        // The previous owner cannot manage the contract anymore
+        assert!(!contract.has_role(Role::Owner, accounts(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot revoke the owner role from the contract owner, use set_owner")]
+    fn test_revoke_owner_role_from_owner() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.grant_role(Role::Owner, accounts(2));
+
+        contract.revoke_role(Role::Owner, accounts(1));
+    }
+
+    #[test]
+    fn test_kyc_check() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.set_tier("KYC_2".to_string(), sample_tier(2, None));
+        contract.set_tier("KYC_3".to_string(), sample_tier(3, None));
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), Some(1700000000), 0, "KYC_2".to_string(), None);
+
+        testing_env!(context
+            .block_timestamp(1664226405000000000)
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
+            .build());
+
+        let token = mint_with_code(&mut contract, 123);
+
+        let expected = KycCheckResult {
+            valid: true,
+            tier: Some("KYC_2".to_string()),
+            expiry: Some(1700000000),
+            token_id: Some(token.token_id.clone()),
+        };
+        assert_eq!(contract.kyc_check(accounts(2), None), expected);
+        assert_eq!(contract.kyc_check(accounts(2), Some("KYC_2".to_string())), expected);
+        assert_eq!(contract.kyc_check(accounts(2), Some(DEFAULT_TIER.to_string())), expected);
+        assert_eq!(
+            serde_json::to_string(&expected).unwrap(),
+            r#"{"valid":true,"tier":"KYC_2","expiry":1700000000,"token_id":"0"}"#
+        );
+
+        // The token is still described when it doesn't satisfy the check
+        assert_eq!(
+            contract.kyc_check(accounts(2), Some("KYC_3".to_string())),
+            KycCheckResult { valid: false, ..expected }
+        );
+        assert_eq!(
+            contract.kyc_check(accounts(3), None),
+            KycCheckResult { valid: false, tier: None, expiry: None, token_id: None }
+        );
+    }
+
+    #[test]
+    fn test_kyc_check_expired() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), Some(1700000000), 0, DEFAULT_TIER.to_string(), None);
+
+        testing_env!(context
+            .block_timestamp(1664226405000000000)
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
+            .build());
+
+        let token = mint_with_code(&mut contract, 123);
+        assert!(contract.kyc_check(accounts(2), None).valid);
+
+        testing_env!(context.block_timestamp(1700000000000000000).build());
+        let result = contract.kyc_check(accounts(2), None);
+        assert!(!result.valid);
+        assert_eq!(result.token_id, Some(token.token_id));
+        assert_eq!(result.expiry, Some(1700000000));
+    }
+
+    #[test]
+    fn test_tier_registry() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.set_tier("KYC_3".to_string(), sample_tier(3, None));
+        contract.set_tier("KYC_2".to_string(), sample_tier(2, None));
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"tier_updated","data":[{"tier":"KYC_2","rank":2,"display_name":"KYC Tier 2","subscription_cost_per_year":null,"requires_reverification":false}]}"#
+        );
+        contract.set_tier_subscription_cost("KYC_2".to_string(), Some(10 * u32::pow(10, SUBSCRIPTION_COST_DECIMALS as u32)));
+
+        let tiers: Vec<String> = contract.get_tiers().into_iter().map(|(tier, _)| tier).collect();
+        assert_eq!(tiers, vec!["KYC_1", "KYC_2", "KYC_3"]);
+        assert_eq!(contract.get_tier("KYC_2".to_string()).unwrap().display_name, "KYC Tier 2");
+
+        // 10 USD per year at 1.7370 USD / NEAR for KYC_2, the default 5 USD for the others
+        let cost = contract.resolve_required_mint_cost(SECS_IN_YEAR as u32, "KYC_2".to_string(), Ok(sample_price_data()));
+        assert_eq!(cost.0, 10 * u128::pow(10, 28) / 17370);
+        let cost = contract.resolve_required_mint_cost(SECS_IN_YEAR as u32, "KYC_3".to_string(), Ok(sample_price_data()));
+        assert_eq!(cost.0, 5 * u128::pow(10, 28) / 17370);
+
+        contract.remove_tier("KYC_3".to_string());
+        assert!(contract.get_tier("KYC_3".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_has_valid_token_with_tier() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.set_tier("KYC_2".to_string(), sample_tier(2, None));
+        contract.set_tier("KYC_3".to_string(), sample_tier(3, None));
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, "KYC_2".to_string(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
+            .build());
+
+        mint_with_code(&mut contract, 123);
+        assert!(contract.has_valid_token_with_tier(accounts(2), DEFAULT_TIER.to_string()));
+        assert!(contract.has_valid_token_with_tier(accounts(2), "KYC_2".to_string()));
+        assert!(!contract.has_valid_token_with_tier(accounts(2), "KYC_3".to_string()));
+        assert!(!contract.has_valid_token_with_tier(accounts(3), DEFAULT_TIER.to_string()));
+
+        // Tokens of a removed tier only satisfy the same tier
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.remove_tier("KYC_2".to_string());
+        assert!(!contract.has_valid_token_with_tier(accounts(2), DEFAULT_TIER.to_string()));
+        assert!(contract.has_valid_token_with_tier(accounts(2), "KYC_2".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown tier")]
+    fn test_authorize_unknown_tier() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, "KYC_2".to_string(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Tier rank already used")]
+    fn test_duplicate_tier_rank() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        contract.set_tier("KYC_2".to_string(), sample_tier(1, None));
+    }
+
+    #[test]
+    #[should_panic(expected = "The subscription cost of a tier can only be changed with set_tier_subscription_cost")]
+    fn test_set_tier_changing_price() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.set_tier("KYC_2".to_string(), sample_tier(2, None));
+        contract.set_tier_subscription_cost("KYC_2".to_string(), Some(1000000000));
+
+        // The current price can be passed along when updating the other fields
+        contract.set_tier("KYC_2".to_string(), sample_tier(2, Some(1000000000)));
+        contract.set_tier("KYC_2".to_string(), sample_tier(2, Some(2000000000)));
+    }
+
+    #[test]
+    fn test_duration_prices() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.set_tier("KYC_2".to_string(), sample_tier(2, None));
+        contract.set_tier_subscription_cost("KYC_2".to_string(), Some(10 * u32::pow(10, SUBSCRIPTION_COST_DECIMALS as u32)));
+        contract.set_duration_prices("KYC_2".to_string(), vec![
+            DurationPrice { min_seconds: SECS_IN_YEAR as u32, subscription_cost_per_year: 8 * u32::pow(10, SUBSCRIPTION_COST_DECIMALS as u32) },
+            DurationPrice { min_seconds: 2 * SECS_IN_YEAR as u32, subscription_cost_per_year: 6 * u32::pow(10, SUBSCRIPTION_COST_DECIMALS as u32) },
+        ]);
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"tier_price_updated","data":[{"tier":"KYC_2","subscription_cost_per_year":1000000000,"duration_prices":[{"min_seconds":31536000,"subscription_cost_per_year":800000000},{"min_seconds":63072000,"subscription_cost_per_year":600000000}]}]}"#
+        );
+
+        assert_eq!(contract.get_subscription_cost_per_year_for("KYC_2".to_string(), SECS_IN_YEAR as u32 / 2), 1000000000);
+        assert_eq!(contract.get_subscription_cost_per_year_for("KYC_2".to_string(), SECS_IN_YEAR as u32), 800000000);
+        assert_eq!(contract.get_subscription_cost_per_year_for("KYC_2".to_string(), 3 * SECS_IN_YEAR as u32), 600000000);
+        assert_eq!(contract.get_subscription_cost_per_year_for(DEFAULT_TIER.to_string(), 3 * SECS_IN_YEAR as u32), 500000000);
+
+        // 2 years at 6 USD per year at 1.7370 USD / NEAR
+        let cost = contract.resolve_required_mint_cost(2 * SECS_IN_YEAR as u32, "KYC_2".to_string(), Ok(sample_price_data()));
+        assert_eq!(cost.0, 6 * u128::pow(10, 28) / 17370 * 2);
+
+        let table = contract.get_pricing_table();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table[0], TierPricing { tier: DEFAULT_TIER.to_string(), subscription_cost_per_year: 500000000, duration_prices: vec![] });
+        assert_eq!(table[1].tier, "KYC_2");
+        assert_eq!(table[1].subscription_cost_per_year, 1000000000);
+        assert_eq!(table[1].duration_prices.len(), 2);
+    }
+
+    #[test]
+    fn test_required_mint_cost_for_code_tier() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.set_tier("KYC_2".to_string(), sample_tier(2, None));
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, SECS_IN_YEAR as u32, "KYC_2".to_string(), None);
+
+        testing_env!(context.build());
+        contract.get_required_mint_cost_for_code(123, accounts(2));
+        let receipts = get_created_receipts();
+        assert!(matches!(
+            &receipts[1].actions[0],
+            VmAction::FunctionCall { function_name, args, .. }
+                if function_name == "resolve_required_mint_cost" && args == br#"{"seconds":31536000,"tier":"KYC_2"}"#
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Duration prices must be in increasing order of min_seconds")]
+    fn test_unordered_duration_prices() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.set_duration_prices(DEFAULT_TIER.to_string(), vec![
+            DurationPrice { min_seconds: 2 * SECS_IN_YEAR as u32, subscription_cost_per_year: 300000000 },
+            DurationPrice { min_seconds: SECS_IN_YEAR as u32, subscription_cost_per_year: 400000000 },
+        ]);
+    }
+
+    /// Mints a token expiring at 1700000000 for charlie and switches to charlie with `deposit` attached
+    fn setup_renewal(context: &mut VMContextBuilder, tier: TierInfo, deposit: Balance) -> (KycdaoNTNFT, TokenId) {
+        context.block_timestamp(1664226405000000000);
+        let mut contract = setup_contract(context);
+        contract.set_tier("KYC_2".to_string(), tier);
+        let token = authorize_and_mint(context, &mut contract, 123, accounts(2), sample_token_metadata("somehash".to_string()), Some(1700000000), "KYC_2");
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(deposit)
+            .build());
+        (contract, token.token_id)
+    }
+
+    fn sign_renewal(auth_code: MintAuthorizationCode, token_id: TokenId, seconds: u32) -> Vec<u8> {
+        use ed25519_dalek::Signer;
+        let payload = RenewalSignaturePayload { auth_code, token_id, seconds, valid_until: SIGNATURE_VALID_UNTIL, contract: accounts(0) };
+        let message = KycdaoNTNFT::signed_message(CHAIN_ID, &payload);
+        signer_keypair().sign(&message).to_bytes().to_vec()
+    }
+
+    #[test]
+    fn test_renew_subscription() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_id) = setup_renewal(&mut context, sample_tier(2, None), 3 * near_sdk::ONE_NEAR);
+
+        contract.renew_subscription(token_id.clone(), SECS_IN_YEAR as u32);
+        let new_expiry = contract.resolve_renew_subscription(token_id.clone(), SECS_IN_YEAR as u32, accounts(2), None, Ok(sample_price_data()));
+
+        assert_eq!(new_expiry, Some(1700000000 + SECS_IN_YEAR as u64));
+        assert_eq!(contract.token_expiry(token_id), Some(1700000000 + SECS_IN_YEAR as u64));
+        assert_eq!(transferred_to(accounts(2)), 3 * near_sdk::ONE_NEAR - paid_mint_cost());
+        assert_eq!(
+            get_logs().last().unwrap(),
+            &format!(
+                r#"EVENT_JSON:{{"standard":"kycdao","version":"1.0.0","event":"subscription_renewed","data":[{{"token_id":"0","owner_id":"charlie","seconds":31536000,"old_expiry":1700000000,"new_expiry":1731536000,"cost":"{}"}}]}}"#,
+                paid_mint_cost()
+            )
+        );
+    }
+
+    #[test]
+    fn test_renew_expired_subscription() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_id) = setup_renewal(&mut context, sample_tier(2, None), 3 * near_sdk::ONE_NEAR);
+        testing_env!(context.block_timestamp(1710000000000000000).build());
+
+        let new_expiry = contract.resolve_renew_subscription(token_id.clone(), SECS_IN_YEAR as u32, accounts(2), None, Ok(sample_price_data()));
+        assert_eq!(new_expiry, Some(1710000000 + SECS_IN_YEAR as u64));
+        assert!(contract.token_is_valid(token_id));
+    }
+
+    #[test]
+    fn test_renew_subscription_insufficient_payment() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_id) = setup_renewal(&mut context, sample_tier(2, None), near_sdk::ONE_NEAR);
+
+        let new_expiry = contract.resolve_renew_subscription(token_id.clone(), SECS_IN_YEAR as u32, accounts(2), None, Ok(sample_price_data()));
+        assert_eq!(new_expiry, None);
+        assert_eq!(contract.token_expiry(token_id), Some(1700000000));
+        assert_eq!(transferred_to(accounts(2)), near_sdk::ONE_NEAR);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the token owner can renew the subscription")]
+    fn test_renew_subscription_not_owner() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_id) = setup_renewal(&mut context, sample_tier(2, None), 3 * near_sdk::ONE_NEAR);
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+
+        contract.renew_subscription(token_id, SECS_IN_YEAR as u32);
+    }
+
+    #[test]
+    #[should_panic(expected = "Tier requires re-verification for renewal")]
+    fn test_renew_subscription_requires_reverification() {
+        let mut context = get_context(accounts(1));
+        let tier = TierInfo { requires_reverification: true, ..sample_tier(2, None) };
+        let (mut contract, token_id) = setup_renewal(&mut context, tier, 3 * near_sdk::ONE_NEAR);
+
+        contract.renew_subscription(token_id, SECS_IN_YEAR as u32);
+    }
+
+    #[test]
+    fn test_renew_subscription_with_signature() {
+        let mut context = get_context(accounts(1));
+        let tier = TierInfo { requires_reverification: true, ..sample_tier(2, None) };
+        let (mut contract, token_id) = setup_renewal(&mut context, tier, 3 * near_sdk::ONE_NEAR);
+
+        let signature = sign_renewal(1, token_id.clone(), SECS_IN_YEAR as u32);
+        contract.renew_subscription_with_signature(token_id.clone(), SECS_IN_YEAR as u32, 1, SIGNATURE_VALID_UNTIL, accounts(1), signature.clone());
+        let digest = KycdaoNTNFT::signed_message(CHAIN_ID, &RenewalSignaturePayload {
+            auth_code: 1,
+            token_id: token_id.clone(),
+            seconds: SECS_IN_YEAR as u32,
+            valid_until: SIGNATURE_VALID_UNTIL,
+            contract: accounts(0),
+        });
+        let new_expiry = contract.resolve_renew_subscription(token_id.clone(), SECS_IN_YEAR as u32, accounts(2), Some(digest.clone()), Ok(sample_price_data()));
+        assert_eq!(new_expiry, Some(1700000000 + SECS_IN_YEAR as u64));
+        assert!(contract.used_renewal_signatures.contains(&digest));
+    }
+
+    #[test]
+    #[should_panic(expected = "Signature already used")]
+    fn test_replayed_renewal_signature() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_id) = setup_renewal(&mut context, sample_tier(2, None), 3 * near_sdk::ONE_NEAR);
+
+        let signature = sign_renewal(1, token_id.clone(), SECS_IN_YEAR as u32);
+        contract.renew_subscription_with_signature(token_id.clone(), SECS_IN_YEAR as u32, 1, SIGNATURE_VALID_UNTIL, accounts(1), signature.clone());
+        contract.renew_subscription_with_signature(token_id, SECS_IN_YEAR as u32, 1, SIGNATURE_VALID_UNTIL, accounts(1), signature);
+    }
+
+    #[test]
+    #[should_panic(expected = "Signature expired")]
+    fn test_expired_renewal_signature() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_id) = setup_renewal(&mut context, sample_tier(2, None), 3 * near_sdk::ONE_NEAR);
+
+        let signature = sign_renewal(1, token_id.clone(), SECS_IN_YEAR as u32);
+        testing_env!(context.block_timestamp(SIGNATURE_VALID_UNTIL * u64::pow(10, 9)).build());
+        contract.renew_subscription_with_signature(token_id, SECS_IN_YEAR as u32, 1, SIGNATURE_VALID_UNTIL, accounts(1), signature);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid signature")]
+    fn test_renewal_signature_for_other_chain() {
+        use ed25519_dalek::Signer;
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_id) = setup_renewal(&mut context, sample_tier(2, None), 3 * near_sdk::ONE_NEAR);
+
+        let payload = RenewalSignaturePayload {
+            auth_code: 1,
+            token_id: token_id.clone(),
+            seconds: SECS_IN_YEAR as u32,
+            valid_until: SIGNATURE_VALID_UNTIL,
+            contract: accounts(0),
+        };
+        let signature = signer_keypair().sign(&KycdaoNTNFT::signed_message("near:mainnet", &payload)).to_bytes().to_vec();
+        contract.renew_subscription_with_signature(token_id, SECS_IN_YEAR as u32, 1, SIGNATURE_VALID_UNTIL, accounts(1), signature);
+    }
+
+    #[test]
+    fn test_signed_message_domains() {
+        // The same bytes signed as a mint and as a renewal give different messages
+        struct OtherPayload(RenewalSignaturePayload);
+        impl BorshSerialize for OtherPayload {
+            fn serialize<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+                self.0.serialize(writer)
+            }
+        }
+        impl SignedPayload for OtherPayload {
+            const DOMAIN: &'static str = MINT_SIGNATURE_DOMAIN;
+        }
+
+        let payload = RenewalSignaturePayload { auth_code: 1, token_id: "0".to_string(), seconds: 1, valid_until: 1, contract: accounts(0) };
+        assert_ne!(
+            KycdaoNTNFT::signed_message(CHAIN_ID, &payload),
+            KycdaoNTNFT::signed_message(CHAIN_ID, &OtherPayload(payload.clone())),
+        );
+    }
+
+    #[test]
+    fn test_burn() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
+            .build());
+        let token = mint_with_code(&mut contract, 123);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .build());
+        let storage_before_burn = env::storage_usage();
+        contract.burn(token.token_id.clone());
+
+        assert!(contract.tokens.owner_by_id.get(&token.token_id).is_none());
+        assert!(contract.token_statuses.get(&token.token_id).is_none());
+        assert!(contract.token_tiers.get(&token.token_id).is_none());
+        assert!(contract.tokens.tokens_per_owner.as_ref().unwrap().get(&accounts(2)).is_none());
+        assert!(contract.token_storage_deposits.get(&token.token_id).is_none());
+        assert!(!contract.has_valid_token(accounts(2)));
+        let freed_storage = storage_before_burn - env::storage_usage();
+        assert!(freed_storage > 0);
+        assert_eq!(transferred_to(accounts(2)), env::storage_byte_cost() * Balance::from(freed_storage));
+        assert_eq!(
+            get_logs(),
+            vec![r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_burn","data":[{"owner_id":"charlie","token_ids":["0"]}]}"#]
+        );
+    }
+
+    #[test]
+    fn test_burn_without_storage_deposit() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_contract(&mut context);
+        // Tokens minted before v0.5.0 have their storage paid by the contract
+        contract.internal_insert_token(&"0".to_string(), &accounts(2), &sample_token_metadata("legacy".to_string()));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.burn("0".to_string());
+        assert!(contract.tokens.owner_by_id.get(&"0".to_string()).is_none());
+        assert_eq!(transferred_to(accounts(2)), 0);
+    }
+
+    #[test]
+    fn test_admin_burn() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
+            .build());
+        let token = mint_with_code(&mut contract, 123);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .signer_account_id(accounts(1))
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.admin_burn(token.token_id.clone(), "Minted in error".to_string());
+
+        assert!(contract.tokens.owner_by_id.get(&token.token_id).is_none());
+        assert!(transferred_to(accounts(2)) > 0);
+        assert_eq!(
+            get_logs(),
+            vec![r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_burn","data":[{"owner_id":"charlie","token_ids":["0"],"authorized_id":"bob","memo":"Minted in error"}]}"#]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the token owner can burn the token")]
+    fn test_burn_not_owner() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .signer_account_id(accounts(2))
+            .predecessor_account_id(accounts(2))
+            .build());
+        let token = mint_with_code(&mut contract, 123);
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.burn(token.token_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must have the revoker role")]
+    fn test_unauthorized_admin_burn() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.admin_burn("0".to_string(), "Minted in error".to_string());
+    }
+
+    /// Mints `count` tokens for separate accounts, every third one revoked and every other one expiring at 1700000000
+    fn setup_token_statuses(context: &mut VMContextBuilder, count: u32) -> KycdaoNTNFT {
+        context.block_timestamp(1664226405000000000);
+        let mut contract = setup_contract(context);
+
+        for i in 0..count {
+            let dst: AccountId = format!("user{}.testnet", i).parse().unwrap();
+            let expiry = if i % 2 == 0 { Some(1700000000) } else { None };
+            let tier = if i % 4 == 0 { "KYC_2" } else { DEFAULT_TIER };
+            let token = authorize_and_mint(context, &mut contract, i, dst, sample_token_metadata("somehash".to_string()), expiry, tier);
+
+            if i % 3 == 0 {
+                testing_env!(context.predecessor_account_id(accounts(1)).build());
+                contract.set_verified_token(token.token_id, false, None, None);
+            }
+        }
+        contract
+    }
+
+    #[test]
+    fn test_tokens_with_status_pagination() {
+        let mut context = get_context(accounts(1));
+        let contract = setup_token_statuses(&mut context, 250);
+
+        let mut token_ids = vec![];
+        let mut from_index = None;
+        let mut pages = 0;
+        loop {
+            let page = contract.tokens_with_status(from_index, Some(1000), None);
+            // The limit is capped to bound the gas used by a single call
+            assert!(page.tokens.len() as u64 <= MAX_TOKEN_STATUS_QUERY_LIMIT);
+            token_ids.extend(page.tokens.into_iter().map(|token| token.token_id));
+            pages += 1;
+            match page.next_index {
+                Some(next_index) => from_index = Some(next_index),
+                None => break,
+            }
+        }
+        assert_eq!(pages, 3);
+        assert_eq!(token_ids.len(), 250);
+
+        let page = contract.tokens_with_status(Some(U128(240)), Some(5), None);
+        assert_eq!(page.tokens.len(), 5);
+        assert_eq!(page.next_index, Some(U128(245)));
+        let page = contract.tokens_with_status(Some(U128(300)), None, None);
+        assert!(page.tokens.is_empty());
+        assert!(page.next_index.is_none());
+
+        // The limit applies to the matching tokens, the scan continues after the last one
+        let revoked = || Some(TokenStatusFilter { verified: Some(false), ..Default::default() });
+        let page = contract.tokens_with_status(None, Some(10), revoked());
+        assert_eq!(page.tokens.len(), 10);
+        assert_eq!(page.tokens.last().unwrap().token_id, "27");
+        assert_eq!(page.next_index, Some(U128(28)));
+        let page = contract.tokens_with_status(Some(U128(28)), None, revoked());
+        assert_eq!(page.tokens.len(), 84 - 10);
+        assert!(page.next_index.is_none());
+
+        // Tokens not matching the filter still count towards the scanned tokens
+        let filter = TokenStatusFilter { tier: Some("KYC_9".to_string()), ..Default::default() };
+        let page = contract.tokens_with_status(None, None, Some(filter));
+        assert!(page.tokens.is_empty());
+        assert!(page.next_index.is_none());
+    }
+
+    #[test]
+    fn test_tokens_with_status_skips_missing_tokens() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_token_statuses(&mut context, 3);
+        contract.token_statuses.insert(&"999".to_string(), &Status::default());
+
+        let page = contract.tokens_with_status(None, None, None);
+        let token_ids: Vec<TokenId> = page.tokens.into_iter().map(|token| token.token_id).collect();
+        assert_eq!(token_ids, vec!["0", "1", "2"]);
+        assert!(page.next_index.is_none());
+    }
+
+    #[test]
+    fn test_tokens_with_status_filters() {
+        let mut context = get_context(accounts(1));
+        let contract = setup_token_statuses(&mut context, 12);
+        let query = |filter: TokenStatusFilter| -> Vec<TokenId> {
+            contract.tokens_with_status(None, None, Some(filter)).tokens.into_iter().map(|token| token.token_id).collect()
+        };
+
+        assert_eq!(query(TokenStatusFilter { verified: Some(false), ..Default::default() }), vec!["0", "3", "6", "9"]);
+        assert_eq!(query(TokenStatusFilter { verified: Some(true), ..Default::default() }).len(), 8);
+        assert_eq!(query(TokenStatusFilter { tier: Some("KYC_2".to_string()), ..Default::default() }), vec!["0", "4", "8"]);
+        assert_eq!(query(TokenStatusFilter { expiring_before: Some(1800000000), ..Default::default() }), vec!["0", "2", "4", "6", "8", "10"]);
+        assert!(query(TokenStatusFilter { expiring_before: Some(1600000000), ..Default::default() }).is_empty());
+        assert!(query(TokenStatusFilter { expired: Some(true), ..Default::default() }).is_empty());
+
+        testing_env!(context.block_timestamp(1700000000000000000).build());
+        assert_eq!(query(TokenStatusFilter { expired: Some(true), verified: Some(true), ..Default::default() }), vec!["2", "4", "8", "10"]);
+
+        let page = contract.tokens_with_status(None, Some(1), None);
+        assert_eq!(page.tokens, vec![TokenStatusView {
+            token_id: "0".to_string(),
+            owner_id: "user0.testnet".parse().unwrap(),
+            tier: "KYC_2".to_string(),
+            expiry: Some(1700000000),
+            verified: false,
+            valid: false,
+        }]);
+    }
+
+    #[test]
+    fn test_kyc_status_of() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(1664226405000000000).build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.set_tier("KYC_2".to_string(), sample_tier(2, None));
+
+        let empty = contract.kyc_status_of(accounts(2));
+        assert_eq!(empty, KycStatus { account_id: accounts(2), valid: false, tokens: vec![], best_token: None, latest_expiry: None });
+
+        let tokens = [
+            (1, Some(1700000000), DEFAULT_TIER),
+            (2, Some(1690000000), "KYC_2"),
+            (3, Some(1800000000), "KYC_2"),
+            (4, None, DEFAULT_TIER),
+        ];
+        for (auth_code, expiry, tier) in tokens {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(AUTHORIZATION_DEPOSIT)
+                .predecessor_account_id(accounts(1))
+                .build());
+            contract.authorize_mint_with_code(auth_code, accounts(2), sample_token_metadata("somehash".to_string()), expiry, 0, tier.to_string(), None);
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+                .predecessor_account_id(accounts(2))
+                .build());
+            mint_with_code(&mut contract, auth_code);
+        }
+        // Revoke the best token, so the other KYC_2 token is picked
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.set_verified_token("2".to_string(), false, None, None);
+
+        let status = contract.kyc_status_of(accounts(2));
+        assert!(status.valid);
+        assert_eq!(status.tokens.len(), 4);
+        assert!(!status.tokens.iter().find(|token| token.token_id == "2").unwrap().valid);
+        assert_eq!(status.best_token, Some(TokenStatusView {
+            token_id: "1".to_string(),
+            owner_id: accounts(2),
+            tier: "KYC_2".to_string(),
+            expiry: Some(1690000000),
+            verified: true,
+            valid: true,
+        }));
+        assert_eq!(status.latest_expiry, Some(1700000000));
+
+        // After the KYC_2 token expires, the token without expiry is the best one
+        testing_env!(context.block_timestamp(1695000000000000000).build());
+        let status = contract.kyc_status_of(accounts(2));
+        assert_eq!(status.best_token.unwrap().token_id, "3");
+        assert_eq!(status.latest_expiry, Some(1700000000));
+    }
+
+    #[test]
+    fn test_revocation_history() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.block_timestamp(1664226405000000000).build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.grant_role(Role::Revoker, accounts(3));
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let token = mint_with_code(&mut contract, 123);
+        assert!(contract.token_revocation_history(token.token_id.clone()).is_empty());
+
+        testing_env!(context.attached_deposit(0).predecessor_account_id(accounts(3)).build());
+        contract.set_verified_token(token.token_id.clone(), false, Some(RevocationReason::Sanctions), Some("Listed on 2022-09-26".to_string()));
+        assert!(!contract.token_is_valid(token.token_id.clone()));
+        assert!(get_logs().last().unwrap().contains(
+            r#""event":"token_revoked","data":[{"token_id":"0","owner_id":"charlie","reason":"sanctions","note":"Listed on 2022-09-26","actor":"danny"}]"#
+        ));
+
+        testing_env!(context
+            .block_timestamp(1665000000000000000)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.set_verified_token(token.token_id.clone(), true, Some(RevocationReason::Resolved), None);
+        assert!(contract.token_is_valid(token.token_id.clone()));
+
+        assert_eq!(contract.token_revocation_history(token.token_id.clone()), vec![
+            RevocationRecord {
+                verified: false,
+                reason: RevocationReason::Sanctions,
+                note: Some("Listed on 2022-09-26".to_string()),
+                actor: accounts(3),
+                timestamp: 1664226405,
+            },
+            RevocationRecord {
+                verified: true,
+                reason: RevocationReason::Resolved,
+                note: None,
+                actor: accounts(1),
+                timestamp: 1665000000,
+            },
+        ]);
+
+        // The history outlives the token
+        contract.admin_burn(token.token_id.clone(), "Sanctions".to_string());
+        assert_eq!(contract.token_revocation_history(token.token_id).len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Note is too long")]
+    fn test_revocation_note_too_long() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let token = mint_with_code(&mut contract, 123);
+
+        testing_env!(context.attached_deposit(0).predecessor_account_id(accounts(1)).build());
+        contract.set_verified_token(token.token_id, false, None, Some("x".repeat(MAX_REVOCATION_NOTE_LENGTH + 1)));
+    }
+
+    #[test]
+    fn test_batch_set_verified() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_token_statuses(&mut context, 4);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let results = contract.batch_set_verified(
+            vec![("1".to_string(), false), ("7".to_string(), false), ("0".to_string(), true)],
+            Some(RevocationReason::Fraud),
+            None,
+        );
+        assert_eq!(results, vec![
+            BatchItemResult { token_id: "1".to_string(), status: BatchItemStatus::Updated },
+            BatchItemResult { token_id: "7".to_string(), status: BatchItemStatus::TokenNotFound },
+            BatchItemResult { token_id: "0".to_string(), status: BatchItemStatus::Updated },
+        ]);
+        assert!(!contract.token_is_verified("1".to_string()));
+        assert!(contract.token_is_verified("0".to_string()));
+        assert_eq!(contract.token_revocation_history("1".to_string())[0].reason, RevocationReason::Fraud);
+        assert_eq!(get_logs().len(), 2);
+    }
+
+    #[test]
+    fn test_batch_update_expiry() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_token_statuses(&mut context, 4);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let results = contract.batch_update_expiry(vec![
+            ("0".to_string(), None),
+            ("1".to_string(), Some(1800000000)),
+            ("missing".to_string(), Some(1800000000)),
+        ]);
+        assert_eq!(
+            results.iter().map(|result| result.status).collect::<Vec<_>>(),
+            vec![BatchItemStatus::Updated, BatchItemStatus::Updated, BatchItemStatus::TokenNotFound]
+        );
+        assert_eq!(contract.token_expiry("0".to_string()), None);
+        assert_eq!(contract.token_expiry("1".to_string()), Some(1800000000));
+    }
+
+    #[test]
+    fn test_batch_skipped_on_low_gas() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_token_statuses(&mut context, 2);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .prepaid_gas(GAS_PER_BATCH_ITEM - Gas(1))
+            .build());
+        let results = contract.batch_update_expiry(vec![("0".to_string(), None), ("1".to_string(), None)]);
+        assert!(results.iter().all(|result| result.status == BatchItemStatus::Skipped));
+        assert_eq!(contract.token_statuses.get(&"0".to_string()).unwrap().expiry, Some(1700000000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Batch is too large, at most 50 items are allowed")]
+    fn test_batch_too_large() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        let tokens = (0..MAX_BATCH_SIZE + 1).map(|i| (i.to_string(), None)).collect();
+        contract.batch_update_expiry(tokens);
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must have the revoker role")]
+    fn test_unauthorized_batch_set_verified() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.batch_set_verified(vec![("0".to_string(), false)], None, None);
+    }
+
+    fn sample_mint_authorization(auth_code: MintAuthorizationCode, dst: AccountId, tier: &str) -> MintAuthorization {
+        MintAuthorization {
+            auth_code,
+            dst,
+            metadata: sample_token_metadata("somehash".to_string()),
+            expiry: Some(1700000000),
+            seconds_to_pay: 0,
+            verification_tier: tier.to_string(),
+            valid_until: None,
+        }
+    }
+
+    #[test]
+    fn test_batch_authorize_mint_with_code() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.authorize_mint_with_code(1, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(near_sdk::ONE_NEAR)
+            .build());
+        let initial_storage_usage = env::storage_usage();
+        let results = contract.batch_authorize_mint_with_code(vec![
+            sample_mint_authorization(1, accounts(2), DEFAULT_TIER),
+            sample_mint_authorization(2, accounts(2), DEFAULT_TIER),
+            sample_mint_authorization(2, accounts(2), DEFAULT_TIER),
+            sample_mint_authorization(1, accounts(3), "KYC_9"),
+            sample_mint_authorization(1, accounts(3), DEFAULT_TIER),
+        ]);
+        assert_eq!(
+            results.iter().map(|result| result.status).collect::<Vec<_>>(),
+            vec![
+                BatchAuthorizationStatus::AlreadyAuthorized,
+                BatchAuthorizationStatus::Authorized,
+                BatchAuthorizationStatus::AlreadyAuthorized,
+                BatchAuthorizationStatus::UnknownTier,
+                BatchAuthorizationStatus::Authorized,
+            ]
+        );
+        assert_eq!(results[4].dst, accounts(3));
+        assert_eq!(get_logs().len(), 2);
+
+        // The storage of the new authorizations is paid from the deposit, the rest is refunded
+        let storage_cost = env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage_usage);
+        assert!(storage_cost > 0);
+        assert_eq!(transferred_to(accounts(1)), near_sdk::ONE_NEAR - storage_cost);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .predecessor_account_id(accounts(3))
+            .build());
+        let token = mint_with_code(&mut contract, 1);
+        assert_eq!(contract.token_expiry(token.token_id), Some(1700000000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Attached deposit must cover the storage of the authorizations")]
+    fn test_batch_authorize_without_deposit() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(0).build());
+        contract.batch_authorize_mint_with_code(vec![sample_mint_authorization(1, accounts(2), DEFAULT_TIER)]);
+    }
+
+    #[test]
+    fn test_authorization_deposit_refunded_on_mint() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(0).build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(AUTHORIZATION_DEPOSIT)
+            .build());
+        let initial_storage_usage = env::storage_usage();
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+        let storage_cost = env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage_usage);
+        assert_eq!(transferred_to(accounts(1)), AUTHORIZATION_DEPOSIT - storage_cost);
+        let digest = contract.pending_digest(123, &accounts(2)).unwrap();
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        mint_with_code(&mut contract, 123);
+        assert_eq!(transferred_to(accounts(1)), storage_cost);
+        assert!(contract.authorization_deposits.get(&digest).is_none());
+        assert!(contract.authorization_digests.get(&(accounts(2), 123)).is_none());
+    }
+
+    #[test]
+    fn test_authorization_deposit_refunded_on_cancel() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.grant_role(Role::Minter, accounts(3));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+        let digest = contract.pending_digest(123, &accounts(2)).unwrap();
+        let deposit = contract.authorization_deposits.get(&digest).unwrap();
+        assert_eq!(deposit.authorizer, accounts(3));
+
+        // The deposit goes back to the authorizer, even if someone else cancels
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.cancel_mint_authorization(123, accounts(2));
+        assert_eq!(transferred_to(accounts(3)), deposit.amount);
+    }
+
+    #[test]
+    #[should_panic(expected = "Attached deposit must cover the storage of the authorizations")]
+    fn test_authorize_without_deposit() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.attached_deposit(0).build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+    }
+
+    #[test]
+    fn test_send_balance_to_safe() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.set_safe_address(accounts(5));
+        contract.set_balance_reserve(U128(2 * near_sdk::ONE_NEAR));
+
+        // Anyone can send the balance to the safe
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .account_balance(10 * near_sdk::ONE_NEAR)
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let locked = env::storage_byte_cost() * Balance::from(env::storage_usage());
+        let expected = 10 * near_sdk::ONE_NEAR - locked - 2 * near_sdk::ONE_NEAR;
+        assert_eq!(contract.get_withdrawable_balance(), U128(expected));
+        assert_eq!(contract.send_balance_to_safe(), U128(expected));
+        assert_eq!(transferred_to(accounts(5)), expected);
+        assert!(get_logs().last().unwrap().contains(r#""event":"balance_sent""#));
+    }
+
+    #[test]
+    #[should_panic(expected = "No balance to send")]
+    fn test_send_balance_keeps_reserve() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        let locked = env::storage_byte_cost() * Balance::from(env::storage_usage());
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .account_balance(locked + DEFAULT_BALANCE_RESERVE)
+            .build());
+        contract.send_balance_to(accounts(5));
+    }
+
+    #[test]
+    #[should_panic(expected = "Safe address is not initialized")]
+    fn test_send_balance_to_safe_without_address() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        contract.send_balance_to_safe();
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must have the owner role")]
+    fn test_unauthorized_set_safe_address() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.set_safe_address(accounts(2));
+    }
+
+    #[test]
+    fn test_config_events() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        testing_env!(context.build());
+        contract.set_safe_address(accounts(5));
+        contract.set_balance_reserve(U128(100));
+        contract.set_price_feed(accounts(4), "wrap.near".to_string());
+        contract.set_price_max_age(60);
+        assert_eq!(get_logs(), vec![
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"safe_address_updated","data":[{"address":"fargo","sender":"bob"}]}"#,
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"balance_reserve_updated","data":[{"reserve":"100","sender":"bob"}]}"#,
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"price_feed_updated","data":[{"address":"eugene","asset_id":"wrap.near","sender":"bob"}]}"#,
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"price_max_age_updated","data":[{"max_age_secs":60,"sender":"bob"}]}"#,
+        ]);
+    }
+
+    /// Mints a token with the given extra and reference metadata for accounts(2)
+    fn setup_token_uri(extra: Option<&str>, reference: Option<&str>) -> (KycdaoNTNFT, TokenId) {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_contract(&mut context);
+        contract.set_base_uri("https://metadata.kycdao.xyz/".to_string());
+        let mut metadata = sample_token_metadata(String::new());
+        metadata.extra = extra.map(|extra| extra.to_string());
+        metadata.reference = reference.map(|reference| reference.to_string());
+        let token = authorize_and_mint(&mut context, &mut contract, 123, accounts(2), metadata, None, DEFAULT_TIER);
+
+        testing_env!(context.attached_deposit(0).predecessor_account_id(accounts(1)).build());
+        (contract, token.token_id)
+    }
+
+    #[test]
+    fn test_set_base_uri() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        contract.set_base_uri("https://gateway.example.com".to_string());
+        assert_eq!(contract.ntnft_metadata().base_uri, Some("https://gateway.example.com".to_string()));
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"token_uri_updated","data":[{"base_uri":"https://gateway.example.com","template":"base_uri"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_token_uri_templates() {
+        let (mut contract, token_id) = setup_token_uri(Some("bafybeigdyrzt"), None);
+
+        assert_eq!(contract.get_token_uri_template(), TokenUriTemplate::BaseUri);
+        assert_eq!(contract.token_uri(token_id.clone()), "https://metadata.kycdao.xyz/bafybeigdyrzt.json");
+
+        contract.set_token_uri_template(TokenUriTemplate::Ipfs);
+        assert_eq!(contract.token_uri(token_id.clone()), "ipfs://bafybeigdyrzt");
+
+        contract.set_base_uri("https://ipfs.io".to_string());
+        contract.set_token_uri_template(TokenUriTemplate::IpfsGateway);
+        assert_eq!(contract.token_uri(token_id.clone()), "https://ipfs.io/ipfs/bafybeigdyrzt");
+
+        contract.set_token_uri_template(TokenUriTemplate::Arweave);
+        assert_eq!(contract.token_uri(token_id), "ar://bafybeigdyrzt");
+        assert!(get_logs().last().unwrap().contains(r#"{"base_uri":"https://ipfs.io","template":"arweave"}"#));
+    }
+
+    #[test]
+    fn test_token_uri_reference_fallback() {
+        // Without a content ID the reference is used
+        let (contract, token_id) = setup_token_uri(None, Some("https://example.com/token.json"));
+        assert_eq!(contract.token_uri(token_id), "https://example.com/token.json");
+    }
+
+    #[test]
+    fn test_token_uri_without_base_uri() {
+        let (mut contract, token_id) = setup_token_uri(Some("bafybeigdyrzt"), Some("https://example.com/token.json"));
+        contract.set_base_uri(String::new());
+        assert_eq!(contract.token_uri(token_id.clone()), "https://example.com/token.json");
+
+        // Templates without a base URI still work
+        contract.set_token_uri_template(TokenUriTemplate::Ipfs);
+        assert_eq!(contract.token_uri(token_id), "ipfs://bafybeigdyrzt");
+    }
+
+    #[test]
+    fn test_token_uri_missing() {
+        let (mut contract, token_id) = setup_token_uri(Some(""), None);
+        assert_eq!(contract.token_uri(token_id.clone()), "");
+        contract.set_token_uri_template(TokenUriTemplate::Arweave);
+        assert_eq!(contract.token_uri(token_id), "");
+    }
+
+    #[test]
+    #[should_panic(expected = "Token not found")]
+    fn test_token_uri_of_missing_token() {
+        let (contract, _) = setup_token_uri(None, None);
+        contract.token_uri("404".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must have the owner role")]
+    fn test_unauthorized_set_token_uri_template() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.set_token_uri_template(TokenUriTemplate::Ipfs);
+    }
+
+    #[test]
+    fn test_authorization_digest_collision() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let other: AccountId = "3alice.near".parse().unwrap();
+
+        // The legacy digests of these authorizations are the same
+        assert_eq!(KycdaoNTNFT::legacy_digest(123, &alice), KycdaoNTNFT::legacy_digest(12, &other));
+        assert_ne!(contract.authorization_digest(123, &alice, 1), contract.authorization_digest(12, &other, 1));
+
+        contract.authorize_mint_with_code(123, alice.clone(), sample_token_metadata("alice".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+        contract.authorize_mint_with_code(12, other.clone(), sample_token_metadata("other".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+        assert_ne!(contract.pending_digest(123, &alice), contract.pending_digest(12, &other));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .predecessor_account_id(other.clone())
+            .build());
+        let token = mint_with_code(&mut contract, 12);
+        assert_eq!(token.metadata.unwrap().extra, Some("other".to_string()));
+        assert!(contract.pending_digest(123, &alice).is_some());
+        assert!(contract.pending_digest(12, &other).is_none());
+    }
+
+    #[test]
+    fn test_authorization_digest_nonce() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+        let first = contract.pending_digest(123, &accounts(2)).unwrap();
+        contract.cancel_mint_authorization(123, accounts(2));
+        assert!(contract.authorization_digests.get(&(accounts(2), 123)).is_none());
+
+        // Authorizing the same code again uses a new nonce
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+        let second = contract.pending_digest(123, &accounts(2)).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(second, contract.authorization_digest(123, &accounts(2), 2));
+    }
+
+    #[test]
+    fn test_migrate_legacy_authorizations() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        // An authorization stored by a previous version
+        let legacy_digest = KycdaoNTNFT::legacy_digest(123, &accounts(2));
+        contract.authorized_token_metadata.insert(&legacy_digest, &sample_token_metadata("somehash".to_string()));
+        contract.authorized_statuses.insert(&legacy_digest, &Status { verified: true, expiry: Some(1700000000) });
+        contract.authorized_seconds_to_pay.insert(&legacy_digest, &0);
+        contract.authorized_tiers.insert(&legacy_digest, &DEFAULT_TIER.to_string());
+        assert_eq!(contract.pending_digest(123, &accounts(2)), Some(legacy_digest.clone()));
+
+        assert_eq!(contract.migrate_legacy_authorizations(vec![(123, accounts(2)), (456, accounts(2))]), 1);
+        let digest = contract.pending_digest(123, &accounts(2)).unwrap();
+        assert_ne!(digest, legacy_digest);
+        assert!(contract.authorized_token_metadata.get(&legacy_digest).is_none());
+        assert!(contract.authorized_statuses.get(&legacy_digest).is_none());
+        assert_eq!(contract.migrate_legacy_authorizations(vec![(123, accounts(2))]), 0);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let token = mint_with_code(&mut contract, 123);
+        assert_eq!(contract.token_expiry(token.token_id), Some(1700000000));
+        // Nothing is refunded for the storage paid by the contract
+        assert_eq!(transferred_to(accounts(0)), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Signature already used")]
+    fn test_legacy_signature_replay() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, keypair) = setup_signature_minting(&mut context);
+        contract.used_mint_signatures.insert(&KycdaoNTNFT::legacy_digest(123, &accounts(2)));
+
+        let signature = sign_mint(&keypair, 123, accounts(2), DEFAULT_TIER.to_string());
+        contract.mint_with_signature(123, sample_token_metadata("somehash".to_string()), Some(9000000000), 0, DEFAULT_TIER.to_string(), accounts(1), signature);
+    }
+
+    #[test]
+    fn test_token_id_prefix() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let metadata = NTNFTContractMetadata {
+            spec: NTNFT_METADATA_SPEC.to_string(),
+            name: "KycDAO Identity".to_string(),
+            symbol: "PEOPLE".to_string(),
+            icon: None,
+            base_uri: None,
+            reference: None,
+            reference_hash: None,
+        };
+        let mut contract = KycdaoNTNFT::new(metadata, Some("kyc-".to_string()), CHAIN_ID.to_string());
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+        assert_eq!(contract.token_id_for_index(U128(0)), "kyc-0");
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let token = mint_with_code(&mut contract, 123);
+        assert_eq!(token.token_id, "kyc-0");
+        assert!(contract.token_is_valid("kyc-0".to_string()));
+        assert_eq!(contract.token_id_for_index(U128(1)), "kyc-1");
+    }
+
+    #[test]
+    #[should_panic(expected = "Token ID prefix is too long")]
+    fn test_token_id_prefix_too_long() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let metadata = NTNFTContractMetadata {
+            spec: NTNFT_METADATA_SPEC.to_string(),
+            name: "KycDAO Identity".to_string(),
+            symbol: "PEOPLE".to_string(),
+            icon: None,
+            base_uri: None,
+            reference: None,
+            reference_hash: None,
+        };
+        KycdaoNTNFT::new(metadata, Some("x".repeat(MAX_TOKEN_ID_PREFIX_LENGTH + 1)), CHAIN_ID.to_string());
+    }
+
+    #[test]
+    fn test_chain_id() {
+        testing_env!(get_context(accounts(1)).build());
+        let contract = KycdaoNTNFT::new_default_meta("base".to_string(), "near:mainnet".to_string());
+        assert_eq!(contract.get_chain_id(), "near:mainnet");
+        assert_eq!(contract.get_price_feed(), "priceoracle.near".parse::<AccountId>().unwrap());
+        assert_eq!(contract.get_price_feed_asset(), "wrap.near");
+
+        let contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        assert_eq!(contract.get_price_feed(), "priceoracle.testnet".parse::<AccountId>().unwrap());
+        assert_eq!(contract.get_price_feed_asset(), "wrap.testnet");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid chain ID")]
+    fn test_invalid_chain_id() {
+        testing_env!(get_context(accounts(1)).build());
+        KycdaoNTNFT::new_default_meta("base".to_string(), "testnet".to_string());
+    }
+
+    #[test]
+    fn test_token_counts_after_burns() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_token_statuses(&mut context, 3);
+
+        // A failed mint does not use up an index
+        testing_env!(context.attached_deposit(AUTHORIZATION_DEPOSIT).predecessor_account_id(accounts(1)).build());
+        contract.authorize_mint_with_code(999, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(0)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.mint_with_code(999);
+        assert!(contract.resolve_mint_with_code(999, accounts(2), Ok(sample_price_data())).is_none());
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.admin_burn("1".to_string(), "Minted in error".to_string());
+        assert_eq!(contract.get_token_counts(), TokenCounts { minted: U128(3), burned: U128(1), total_supply: U128(2) });
+        assert_eq!(contract.ntnft_total_supply(), U128(2));
+
+        // Burned indices are not reused
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
+            .predecessor_account_id(accounts(2))
+            .build());
+        let token = mint_with_code(&mut contract, 999);
+        assert_eq!(token.token_id, contract.token_id_for_index(U128(3)));
+        assert_eq!(contract.get_token_counts(), TokenCounts { minted: U128(4), burned: U128(1), total_supply: U128(3) });
+    }
+
+    /// Sets the token policy and mints a token to charlie, who has another authorized code (2)
+    fn setup_token_policy(context: &mut VMContextBuilder, policy: TokenPolicy) -> KycdaoNTNFT {
+        let mut contract = setup_contract(context);
+        contract.set_token_policy(policy);
+        contract.authorize_mint_with_code(2, accounts(2), sample_token_metadata("second".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+        authorize_and_mint(context, &mut contract, 1, accounts(2), sample_token_metadata("first".to_string()), None, DEFAULT_TIER);
+        // Revoking the first token needs a deposit for its revocation record as well
+        let replacement_deposit = env::storage_byte_cost() * Balance::from(MAX_REPLACEMENT_RECORD_STORAGE);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(MINT_STORAGE_COST + MINT_COST + replacement_deposit)
+            .build());
+        contract
+    }
 
-    const MINT_STORAGE_COST: u128 = 6080000000000000000000;
-    //const MINT_COST: u128 = near_sdk::ONE_NEAR;
-    const MINT_COST: u128 = 0;
+    #[test]
+    #[should_panic(expected = "Account already has a valid token")]
+    fn test_token_policy_reject_if_valid() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_token_policy(&mut context, TokenPolicy::RejectIfValid);
+        assert_eq!(contract.get_token_policy(), TokenPolicy::RejectIfValid);
 
-    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
-        let mut builder = VMContextBuilder::new();
-        builder
-            .current_account_id(accounts(0))
-            .signer_account_id(predecessor_account_id.clone())
-            .predecessor_account_id(predecessor_account_id);
-        builder
+        contract.mint_with_code(2);
     }
 
-    fn sample_token_metadata(extra: String) -> TokenMetadata {
-        TokenMetadata {
-            title: Some("Olympus Mons".into()),
-            description: Some("The tallest mountain in the charted solar system".into()),
-            media: None,
-            media_hash: None,
-            copies: Some(1u64),
-            issued_at: None,
-            expires_at: None,
-            starts_at: None,
-            updated_at: None,
-            extra: Some(extra),
-            reference: None,
-            reference_hash: None,
+    #[test]
+    fn test_token_policy_reject_if_valid_after_revocation() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_token_policy(&mut context, TokenPolicy::RejectIfValid);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.set_verified_token("0".to_string(), false, Some(RevocationReason::DocumentsInvalid), None);
+
+        testing_env!(context.storage_usage(env::storage_usage()).predecessor_account_id(accounts(2)).build());
+        let token = mint_with_code(&mut contract, 2);
+        assert!(contract.token_is_valid(token.token_id));
+        assert_eq!(contract.kyc_status_of(accounts(2)).tokens.len(), 2);
+    }
+
+    #[test]
+    fn test_token_policy_reject_if_valid_on_resolve() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_token_policy(&mut context, TokenPolicy::RejectIfValid);
+
+        // The first token was minted while waiting for the price of the second mint
+        assert!(contract.resolve_mint_with_code(2, accounts(2), Ok(sample_price_data())).is_none());
+        assert_eq!(contract.kyc_status_of(accounts(2)).tokens.len(), 1);
+        assert_eq!(transferred_to(accounts(2)), MINT_STORAGE_COST + MINT_COST);
+        assert!(get_logs().contains(&"Minting failed: Account already has a valid token".to_string()));
+    }
+
+    #[test]
+    fn test_token_policy_revoke_previous() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_token_policy(&mut context, TokenPolicy::RevokePrevious);
+
+        let token = mint_with_code(&mut contract, 2);
+        assert_eq!(token.token_id, "1");
+        assert!(contract.token_is_valid("1".to_string()));
+        assert!(!contract.token_is_valid("0".to_string()));
+        assert_eq!(contract.kyc_status_of(accounts(2)).tokens.len(), 2);
+
+        let history = contract.token_revocation_history("0".to_string());
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].reason, RevocationReason::Superseded);
+        assert_eq!(history[0].note, Some("Replaced by token 1".to_string()));
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"token_revoked","data":[{"token_id":"0","owner_id":"charlie","reason":"superseded","note":"Replaced by token 1","actor":"charlie"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_token_policy_revoke_previous_storage_is_paid_by_minter() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_token_policy(&mut context, TokenPolicy::RevokePrevious);
+        let initial_storage_usage = env::storage_usage();
+
+        mint_with_code(&mut contract, 2);
+        // The refund does not include the storage of the new token or of the revocation record of the first one
+        let storage_cost = env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage_usage);
+        assert_eq!(transferred_to(accounts(2)), env::attached_deposit() - MINT_COST - storage_cost);
+    }
+
+    #[test]
+    fn test_token_policy_revoke_previous_too_many_tokens_while_minting() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_token_policy(&mut context, TokenPolicy::RevokePrevious);
+        contract.mint_with_code(2);
+
+        // Other tokens are minted to the account while waiting for the price
+        for i in 0..MAX_BATCH_SIZE {
+            contract.internal_insert_token(&format!("extra{}", i), &accounts(2), &sample_token_metadata("extra".to_string()));
         }
+        let token = contract.resolve_mint_with_code(2, accounts(2), Ok(sample_price_data()));
+        assert!(token.is_none());
+        assert!(contract.token_is_valid("0".to_string()));
+        assert_eq!(transferred_to(accounts(2)), env::attached_deposit());
+        assert_eq!(get_logs().last().unwrap(), "Minting failed: Account has too many tokens to replace, burn some first");
     }
 
-    // TODO fix this - contract state is not deleted between tests
     #[test]
-    #[ignore]
-    fn test_new() {
+    #[should_panic(expected = "Account has too many tokens to replace, burn some first")]
+    fn test_token_policy_revoke_previous_too_many_tokens() {
         let mut context = get_context(accounts(1));
-        testing_env!(context.build());
-        let contract = KycdaoNTNFT::new_default_meta("base2".to_string());
-        testing_env!(context.is_view(true).build());
-        assert_eq!(contract.ntnft_token("1".to_string()), None);
+        let mut contract = setup_token_policy(&mut context, TokenPolicy::RevokePrevious);
+        for i in 0..MAX_BATCH_SIZE {
+            contract.internal_insert_token(&format!("extra{}", i), &accounts(2), &sample_token_metadata("extra".to_string()));
+        }
+
+        contract.mint_with_code(2);
     }
 
     #[test]
-    #[should_panic(expected = "The contract is not initialized")]
-    fn test_default() {
-        let context = get_context(accounts(1));
-        testing_env!(context.build());
-        let _contract = KycdaoNTNFT::default();
+    fn test_token_policy_burn_previous() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_token_policy(&mut context, TokenPolicy::BurnPrevious);
+
+        let token = mint_with_code(&mut contract, 2);
+        assert!(contract.ntnft_token("0".to_string()).is_none());
+        let tokens = contract.kyc_status_of(accounts(2)).tokens;
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].token_id, token.token_id);
+        assert_eq!(contract.get_token_counts(), TokenCounts { minted: U128(2), burned: U128(1), total_supply: U128(1) });
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"nep171","version":"1.0.0","event":"nft_burn","data":[{"owner_id":"charlie","token_ids":["0"],"memo":"Replaced by token 1"}]}"#
+        );
     }
 
     #[test]
-    fn test_metadata() {
+    #[should_panic(expected = "Account already has a valid token")]
+    fn test_token_policy_signature_minting() {
         let mut context = get_context(accounts(1));
-        testing_env!(context.build());
-        let contract = KycdaoNTNFT::new_default_meta("base".to_string());
-        testing_env!(context.is_view(true).build());
-        assert_eq!(contract.ntnft_metadata().base_uri, Some("base".to_string()));
+        let (mut contract, keypair) = setup_signature_minting(&mut context);
+        let signature = sign_mint(&keypair, 123, accounts(2), DEFAULT_TIER.to_string());
+        contract.mint_with_signature(123, sample_token_metadata("somehash".to_string()), Some(9000000000), 0, DEFAULT_TIER.to_string(), accounts(1), signature);
+        let payload = sample_mint_payload(123, accounts(2), DEFAULT_TIER.to_string());
+        contract.resolve_mint_with_signature(payload, Ok(sample_price_data())).expect("Minting failed");
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.set_token_policy(TokenPolicy::RejectIfValid);
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"token_policy_updated","data":[{"policy":"reject_if_valid"}]}"#
+        );
+
+        testing_env!(context.storage_usage(env::storage_usage()).predecessor_account_id(accounts(2)).build());
+        let signature = sign_mint(&keypair, 124, accounts(2), DEFAULT_TIER.to_string());
+        contract.mint_with_signature(124, sample_token_metadata("somehash".to_string()), Some(9000000000), 0, DEFAULT_TIER.to_string(), accounts(1), signature);
     }
 
     #[test]
-    fn test_authorized_minting() {
+    #[should_panic(expected = "Predecessor must have the owner role")]
+    fn test_unauthorized_set_token_policy() {
         let mut context = get_context(accounts(1));
         testing_env!(context.build());
-        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.set_token_policy(TokenPolicy::BurnPrevious);
+    }
 
-        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string());
-        contract.authorize_mint_with_code(365, accounts(2), sample_token_metadata("othersomehash".to_string()), None, 0, DEFAULT_TIER.to_string());
+    /// Mints a token to charlie and authorizes its upgrade to KYC_2 with code 7, paying for `seconds_to_pay`
+    fn setup_token_update(context: &mut VMContextBuilder, seconds_to_pay: u32) -> (KycdaoNTNFT, TokenId) {
+        context.block_timestamp(1664226405000000000);
+        let mut contract = setup_contract(context);
+        let token = authorize_and_mint(context, &mut contract, 1, accounts(2), sample_token_metadata("first".to_string()), Some(1700000000), DEFAULT_TIER);
 
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
-            .signer_account_id(accounts(2))
-            .predecessor_account_id(accounts(2))
+            .attached_deposit(AUTHORIZATION_DEPOSIT)
+            .predecessor_account_id(accounts(1))
             .build());
-
-        let token = contract.mint_with_code(365);
-        assert_eq!(token.token_id, "0".to_string());
-        assert_eq!(token.owner_id, accounts(2));
-        assert_eq!(token.metadata.unwrap(), sample_token_metadata("othersomehash".to_string()));
+        contract.authorize_token_update(token.token_id.clone(), 7, sample_token_metadata("upgraded".to_string()), "KYC_2".to_string(), Some(9000000000), seconds_to_pay);
 
         testing_env!(context
             .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
-            .signer_account_id(accounts(2))
+            .attached_deposit(MINT_STORAGE_COST)
             .predecessor_account_id(accounts(2))
             .build());
+        (contract, token.token_id)
+    }
 
-        let token = contract.mint_with_code(123);
-        assert_eq!(token.token_id, "1".to_string());
-        assert_eq!(token.owner_id, accounts(2));
-        assert_eq!(token.metadata.unwrap(), sample_token_metadata("somehash".to_string()));
+    #[test]
+    fn test_token_update() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_id) = setup_token_update(&mut context, 0);
+        let update = contract.get_token_update(token_id.clone()).expect("Update not found");
+        assert_eq!(update.tier, "KYC_2");
+        assert_eq!(update.authorizer, accounts(1));
+        assert!(update.deposit.0 > 0);
+
+        contract.apply_token_update(token_id.clone(), 7);
+        let token = contract.resolve_token_update(token_id.clone(), 7, accounts(2), Ok(sample_price_data())).expect("Update failed");
+
+        let metadata = token.metadata.unwrap();
+        assert_eq!(metadata.extra, Some("upgraded".to_string()));
+        assert_eq!(metadata.updated_at, Some("1664226405000".to_string()));
+        assert_eq!(contract.token_tier(token_id.clone()), "KYC_2");
+        assert_eq!(contract.token_expiry(token_id.clone()), Some(9000000000));
+        assert!(contract.token_is_valid(token_id.clone()));
+        assert_eq!(contract.get_token_update(token_id), None);
+
+        // The storage deposit of the update is refunded to the authorizer
+        assert_eq!(transferred_to(accounts(1)), update.deposit.0);
+        let logs = get_logs();
+        assert!(logs.contains(&r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"tier_changed","data":[{"token_id":"0","old_tier":"KYC_1","new_tier":"KYC_2"}]}"#.to_string()));
+        assert!(logs.contains(&r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"expiry_updated","data":[{"token_id":"0","old_expiry":1700000000,"new_expiry":9000000000}]}"#.to_string()));
+        assert_eq!(
+            logs.last().unwrap(),
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"token_updated","data":[{"token_id":"0","owner_id":"charlie","tier":"KYC_2","expiry":9000000000,"cost":"0"}]}"#
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Unauthorized code")]
-    fn test_unauthorized_minting() {
+    fn test_token_update_insufficient_payment() {
         let mut context = get_context(accounts(1));
-        testing_env!(context.build());
-        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string());
+        let (mut contract, token_id) = setup_token_update(&mut context, SECS_IN_YEAR as u32);
 
-        testing_env!(context
-            .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
-            .signer_account_id(accounts(2))
-            .predecessor_account_id(accounts(2))
-            .build());
+        assert!(contract.resolve_token_update(token_id.clone(), 7, accounts(2), Ok(sample_price_data())).is_none());
+        assert_eq!(transferred_to(accounts(2)), MINT_STORAGE_COST);
+        assert_eq!(contract.token_tier(token_id.clone()), DEFAULT_TIER);
+        assert_eq!(contract.token_expiry(token_id.clone()), Some(1700000000));
+        assert_eq!(contract.ntnft_token(token_id.clone()).unwrap().metadata.unwrap(), sample_token_metadata("first".to_string()));
+        assert!(contract.get_token_update(token_id).is_some());
 
-        contract.mint_with_code(123);
+        // Paying for the subscription of the new tier completes the update
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(MINT_STORAGE_COST + paid_mint_cost()).build());
+        assert!(contract.resolve_token_update("0".to_string(), 7, accounts(2), Ok(sample_price_data())).is_some());
     }
 
-    // TODO fix this - contract state is not deleted between tests
     #[test]
-    #[ignore]
-    fn test_status_modifications() {
-        let mut context = get_context(accounts(3));
-        testing_env!(context.build());
-        let mut contract = KycdaoNTNFT::new_default_meta("base3".to_string());
+    fn test_failed_token_update_keeps_missing_entries() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_id) = setup_token_update(&mut context, SECS_IN_YEAR as u32);
+        // Tokens minted before tiers were added have no tier entry
+        contract.token_tiers.remove(&token_id);
+        contract.token_statuses.remove(&token_id);
 
-        // use default status fallback
-        contract.authorize_mint_with_code(489, accounts(3), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string());
+        assert!(contract.resolve_token_update(token_id.clone(), 7, accounts(2), Ok(sample_price_data())).is_none());
+        assert!(contract.token_tiers.get(&token_id).is_none());
+        assert!(contract.token_statuses.get(&token_id).is_none());
+        assert_eq!(contract.ntnft_token(token_id).unwrap().metadata.unwrap(), sample_token_metadata("first".to_string()));
+    }
 
-        testing_env!(context
-            .block_timestamp(1664226405000000000)
-            .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
-            .signer_account_id(accounts(3))
-            .predecessor_account_id(accounts(3))
-            .build());
+    #[test]
+    #[should_panic(expected = "Unauthorized code")]
+    fn test_token_update_wrong_code() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_id) = setup_token_update(&mut context, 0);
 
-        let token = contract.mint_with_code(489);
+        contract.apply_token_update(token_id, 8);
+    }
 
-        assert_eq!(contract.token_expiry(token.token_id.clone()), None);
-        assert_eq!(contract.token_statuses.get(&token.token_id).unwrap().verified, true);
-        assert_eq!(contract.token_is_valid(token.token_id.clone()), true);
-        assert_eq!(contract.has_valid_token(accounts(3)), true);
+    #[test]
+    #[should_panic(expected = "Only the token owner can apply the update")]
+    fn test_token_update_not_owner() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_id) = setup_token_update(&mut context, 0);
 
-        contract.update_expiry(token.token_id.clone(), Some(1000));
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.apply_token_update(token_id, 7);
+    }
 
-        assert_eq!(contract.token_expiry(token.token_id.clone()), Some(1000));
-        assert_eq!(contract.token_statuses.get(&token.token_id).unwrap().verified, true);
-        assert_eq!(contract.token_is_valid(token.token_id.clone()), false);
-        assert_eq!(contract.has_valid_token(accounts(3)), false);
+    #[test]
+    fn test_cancel_token_update() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_id) = setup_token_update(&mut context, 0);
+        let deposit = contract.get_token_update(token_id.clone()).unwrap().deposit;
 
-        contract.update_expiry(token.token_id.clone(), Some(9000000000));
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.cancel_token_update(token_id.clone());
+        assert_eq!(transferred_to(accounts(1)), deposit.0);
+        assert_eq!(contract.get_token_update(token_id.clone()), None);
 
-        assert_eq!(contract.token_expiry(token.token_id.clone()), Some(9000000000));
-        assert_eq!(contract.token_statuses.get(&token.token_id).unwrap().verified, true);
-        assert_eq!(contract.token_is_valid(token.token_id.clone()), true);
-        assert_eq!(contract.has_valid_token(accounts(3)), true);
+        // The cancelled update cannot be applied
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        assert!(contract.resolve_token_update(token_id, 7, accounts(2), Ok(sample_price_data())).is_none());
+        assert!(get_logs().contains(&"Token update failed: Unauthorized code".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Predecessor must have the minter role")]
+    fn test_unauthorized_authorize_token_update() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_id) = setup_token_update(&mut context, 0);
 
-        contract.set_verified_token(token.token_id.clone(), false);
+        contract.authorize_token_update(token_id, 8, sample_token_metadata("forged".to_string()), "KYC_2".to_string(), None, 0);
+    }
 
-        assert_eq!(contract.token_expiry(token.token_id.clone()), Some(9000000000));
-        assert_eq!(contract.token_statuses.get(&token.token_id).unwrap().verified, false);
-        assert_eq!(contract.token_is_valid(token.token_id.clone()), false);
-        assert_eq!(contract.has_valid_token(accounts(3)), false);
+    fn setup_paused(context: &mut VMContextBuilder, feature: PausableFeature) -> KycdaoNTNFT {
+        let mut contract = setup_contract(context);
+        contract.pause(feature);
+        contract
+    }
 
-        contract.authorize_mint_with_code(789, accounts(3), sample_token_metadata("other".to_string()), None, 0, DEFAULT_TIER.to_string());
+    #[test]
+    fn test_pause() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_paused(&mut context, PausableFeature::Minting);
+        assert!(contract.is_paused(PausableFeature::Minting));
+        assert!(!contract.is_paused(PausableFeature::Authorization));
+        assert_eq!(
+            get_logs(),
+            vec![r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"paused","data":[{"feature":"minting","actor":"bob"}]}"#]
+        );
+
+        // Pausing again is a no-op
+        contract.pause(PausableFeature::Minting);
+        contract.pause(PausableFeature::Renewal);
+        assert_eq!(contract.get_paused_features(), vec![PausableFeature::Minting, PausableFeature::Renewal]);
+
+        contract.unpause(PausableFeature::Minting);
+        assert_eq!(contract.get_paused_features(), vec![PausableFeature::Renewal]);
+        assert_eq!(
+            get_logs().last().unwrap(),
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"unpaused","data":[{"feature":"minting","actor":"bob"}]}"#
+        );
 
+        // Authorizations work while minting is paused, and minting works again after unpausing
+        contract.pause(PausableFeature::Minting);
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+        contract.unpause(PausableFeature::Minting);
         testing_env!(context
-            .block_timestamp(1664226405000000000)
             .storage_usage(env::storage_usage())
             .attached_deposit(MINT_STORAGE_COST + MINT_COST)
-            .signer_account_id(accounts(3))
-            .predecessor_account_id(accounts(3))
+            .predecessor_account_id(accounts(2))
             .build());
+        mint_with_code(&mut contract, 123);
+    }
 
-        let token_new = contract.mint_with_code(789);
+    #[test]
+    fn test_guardian_pause() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.grant_role(Role::Guardian, accounts(3));
 
-        assert_eq!(contract.token_is_valid(token.token_id.clone()), false);
-        assert_eq!(contract.token_is_valid(token_new.token_id.clone()), true);
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.pause(PausableFeature::Withdrawal);
+        assert!(contract.is_paused(PausableFeature::Withdrawal));
+    }
 
-        // default is valid
-        assert_eq!(contract.has_valid_token(accounts(3)), true);
+    #[test]
+    #[should_panic(expected = "Predecessor must have the owner role")]
+    fn test_guardian_cannot_unpause() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_paused(&mut context, PausableFeature::Minting);
+        contract.grant_role(Role::Guardian, accounts(3));
+
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.unpause(PausableFeature::Minting);
     }
 
     #[test]
-    #[should_panic(expected = "Predecessor must be Mint Authorizer")]
-    fn test_unauthorized_expiry_change() {
-        let mut context = get_context(accounts(3));
+    #[should_panic(expected = "Predecessor must have the owner or guardian role")]
+    fn test_unauthorized_pause() {
+        let mut context = get_context(accounts(1));
         testing_env!(context.build());
-        let mut contract = KycdaoNTNFT::new_default_meta("base3".to_string());
+        let mut contract = KycdaoNTNFT::new_default_meta("base".to_string(), CHAIN_ID.to_string());
+        contract.grant_role(Role::Minter, accounts(2));
 
-        // use default status fallback
-        contract.authorize_mint_with_code(489, accounts(3), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string());
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.pause(PausableFeature::Minting);
+    }
 
-        testing_env!(context
-            .block_timestamp(1664226405000000000)
-            .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
-            .signer_account_id(accounts(3))
-            .predecessor_account_id(accounts(3))
-            .build());
+    #[test]
+    #[should_panic(expected = "Minting is paused")]
+    fn test_paused_mint_with_code() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_paused(&mut context, PausableFeature::Minting);
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
 
-        let token = contract.mint_with_code(489);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.mint_with_code(123);
+    }
 
-        testing_env!(context
-            .block_timestamp(1664226405000000000)
-            .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
-            .signer_account_id(accounts(4))
-            .predecessor_account_id(accounts(4))
-            .build());
+    #[test]
+    #[should_panic(expected = "Minting is paused")]
+    fn test_paused_mint_with_signature() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, keypair) = setup_signature_minting(&mut context);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.pause(PausableFeature::Minting);
 
-        contract.update_expiry(token.token_id.clone(), Some(1000));
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        let signature = sign_mint(&keypair, 123, accounts(2), DEFAULT_TIER.to_string());
+        contract.mint_with_signature(123, sample_token_metadata("somehash".to_string()), Some(9000000000), 0, DEFAULT_TIER.to_string(), accounts(1), signature);
     }
 
     #[test]
-    #[should_panic(expected = "Predecessor must be Mint Authorizer")]
-    fn test_unauthorized_revoke() {
-        let mut context = get_context(accounts(3));
-        testing_env!(context.build());
-        let mut contract = KycdaoNTNFT::new_default_meta("base3".to_string());
+    #[should_panic(expected = "Minting is paused")]
+    fn test_paused_apply_token_update() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_id) = setup_token_update(&mut context, 0);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.pause(PausableFeature::Minting);
 
-        // use default status fallback
-        contract.authorize_mint_with_code(489, accounts(3), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string());
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.apply_token_update(token_id, 7);
+    }
 
-        testing_env!(context
-            .block_timestamp(1664226405000000000)
-            .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
-            .signer_account_id(accounts(3))
-            .predecessor_account_id(accounts(3))
-            .build());
+    #[test]
+    #[should_panic(expected = "Authorization is paused")]
+    fn test_paused_authorize_mint_with_code() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_paused(&mut context, PausableFeature::Authorization);
 
-        let token = contract.mint_with_code(489);
+        contract.authorize_mint_with_code(123, accounts(2), sample_token_metadata("somehash".to_string()), None, 0, DEFAULT_TIER.to_string(), None);
+    }
 
-        testing_env!(context
-            .block_timestamp(1664226405000000000)
-            .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
-            .signer_account_id(accounts(4))
-            .predecessor_account_id(accounts(4))
-            .build());
+    #[test]
+    #[should_panic(expected = "Authorization is paused")]
+    fn test_paused_batch_authorize_mint_with_code() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_paused(&mut context, PausableFeature::Authorization);
 
-        contract.set_verified_token(token.token_id.clone(), false);
+        contract.batch_authorize_mint_with_code(vec![sample_mint_authorization(123, accounts(2), DEFAULT_TIER)]);
     }
 
     #[test]
-    #[ignore]
-    fn test_status_setting_on_authorization() {
-        let mut context = get_context(accounts(4));
-        testing_env!(context.build());
-        let mut contract = KycdaoNTNFT::new_default_meta("base3".to_string());
+    #[should_panic(expected = "Authorization is paused")]
+    fn test_paused_authorize_token_update() {
+        let mut context = get_context(accounts(1));
+        let (mut contract, token_id) = setup_token_update(&mut context, 0);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.pause(PausableFeature::Authorization);
 
-        // use default status fallback
-        contract.authorize_mint_with_code(6547, accounts(4), sample_token_metadata("somehash".to_string()), Some(9000000000), 0, DEFAULT_TIER.to_string());
+        contract.authorize_token_update(token_id, 8, sample_token_metadata("upgraded".to_string()), "KYC_2".to_string(), None, 0);
+    }
 
-        testing_env!(context
-            .block_timestamp(1664226405000000000)
-            .storage_usage(env::storage_usage())
-            .attached_deposit(MINT_STORAGE_COST + MINT_COST)
-            .signer_account_id(accounts(4))
-            .predecessor_account_id(accounts(4))
-            .build());
+    #[test]
+    #[should_panic(expected = "Renewal is paused")]
+    fn test_paused_renew_subscription() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_paused(&mut context, PausableFeature::Renewal);
+
+        contract.renew_subscription("0".to_string(), 60);
+    }
 
-        let token = contract.mint_with_code(6547);
+    #[test]
+    #[should_panic(expected = "Renewal is paused")]
+    fn test_paused_renew_subscription_with_signature() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_paused(&mut context, PausableFeature::Renewal);
 
-        assert_eq!(contract.token_expiry(token.token_id.clone()), Some(9000000000));
-        assert_eq!(contract.token_statuses.get(&token.token_id).unwrap().verified, true);
-        assert_eq!(contract.has_valid_token(accounts(4)), true);
+        contract.renew_subscription_with_signature("0".to_string(), 60, 123, SIGNATURE_VALID_UNTIL, accounts(1), sign_renewal(123, "0".to_string(), 60));
+    }
+
+    #[test]
+    #[should_panic(expected = "Withdrawal is paused")]
+    fn test_paused_send_balance_to_safe() {
+        let mut context = get_context(accounts(1));
+        let mut contract = setup_paused(&mut context, PausableFeature::Withdrawal);
+        contract.set_safe_address(accounts(5));
+
+        contract.send_balance_to_safe();
+    }
+
+    #[test]
+    #[should_panic(expected = "Withdrawal is paused")]
+    fn test_paused_send_balance_to() {
+        let mut context = get_context(accounts(1));
+        let contract = setup_paused(&mut context, PausableFeature::Withdrawal);
+
+        contract.send_balance_to(accounts(5));
     }
 }
@@ -0,0 +1,426 @@
+//! Storage layouts of previously deployed versions of the contract, and the
+//! migrations which bring them up to the current layout.
+//!
+//! Every layout is migrated to the next one in order, so only one step has to be
+//! written when the layout changes: add the last layout here and migrate it to
+//! `KycdaoNTNFT`, then make the previous step migrate to the added layout.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, LookupSet, LazyOption, UnorderedMap};
+use near_sdk::{env, AccountId};
+use near_contract_standards::ntnft::{NTNFT, TokenId};
+use near_contract_standards::ntnft::metadata::*;
+use std::str::FromStr;
+
+use crate::price_feed::{PriceFeed, PriceFeedMocked, PriceFeedV0_4_1};
+use crate::events::{Event, StorageVersionUpdated};
+use crate::roles::{Role, Roles};
+use crate::{KycdaoNTNFT, Status, StorageKey, TokenPolicy, TokenUriTemplate, DEFAULT_BALANCE_RESERVE, SUBSCRIPTION_COST_DECIMALS, VERSION};
+
+/// Status of a token in v0.3.2
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StatusV0_3_2 {
+    pub is_revoked: bool,
+    /// expiry timestamp (epoch time in seconds)
+    pub expiry: Option<u64>,
+}
+
+impl From<StatusV0_3_2> for Status {
+    fn from(old: StatusV0_3_2) -> Self {
+        Status {
+            verified: !old.is_revoked,
+            expiry: old.expiry,
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct KycdaoNTNFTV0_3_2 {
+    tokens: NTNFT,
+    metadata: LazyOption<NTNFTContractMetadata>,
+    next_token_id: u128,
+    mint_authorizer: AccountId,
+    authorized_token_metadata: LookupMap<Vec<u8>, TokenMetadata>,
+    authorized_statuses: UnorderedMap<Vec<u8>, StatusV0_3_2>,
+    token_statuses: UnorderedMap<TokenId, StatusV0_3_2>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct KycdaoNTNFTV0_4_1 {
+    tokens: NTNFT,
+    metadata: LazyOption<NTNFTContractMetadata>,
+    next_token_id: u128,
+    mint_authorizer: AccountId,
+    authorized_token_metadata: LookupMap<Vec<u8>, TokenMetadata>,
+    authorized_statuses: UnorderedMap<Vec<u8>, Status>,
+    token_statuses: UnorderedMap<TokenId, Status>,
+    subscription_cost_per_year: u32,
+    authorized_seconds_to_pay: UnorderedMap<Vec<u8>, u32>,
+    authorized_tiers: UnorderedMap<Vec<u8>, String>,
+    token_tiers: UnorderedMap<TokenId, String>,
+    native_usd_price_feed: PriceFeedV0_4_1,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct KycdaoNTNFTV0_4_2 {
+    tokens: NTNFT,
+    metadata: LazyOption<NTNFTContractMetadata>,
+    next_token_id: u128,
+    mint_authorizer: AccountId,
+    authorized_token_metadata: LookupMap<Vec<u8>, TokenMetadata>,
+    authorized_statuses: UnorderedMap<Vec<u8>, Status>,
+    token_statuses: UnorderedMap<TokenId, Status>,
+    subscription_cost_per_year: u32,
+    authorized_seconds_to_pay: UnorderedMap<Vec<u8>, u32>,
+    authorized_tiers: UnorderedMap<Vec<u8>, String>,
+    token_tiers: UnorderedMap<TokenId, String>,
+    native_usd_price_feed: PriceFeedMocked,
+}
+
+/// Statuses were stored as `is_revoked` under different storage keys in v0.3.2,
+/// so they are copied over to the new maps. Tiers are not stored, as missing tiers
+/// default to DEFAULT_TIER.
+impl From<KycdaoNTNFTV0_3_2> for KycdaoNTNFTV0_4_1 {
+    fn from(old: KycdaoNTNFTV0_3_2) -> Self {
+        let mut old_authorized_statuses = old.authorized_statuses;
+        let mut authorized_statuses = UnorderedMap::new(StorageKey::AuthorizedStatuses);
+        for (digest, status) in old_authorized_statuses.iter() {
+            authorized_statuses.insert(&digest, &Status::from(status));
+        }
+        old_authorized_statuses.clear();
+
+        let mut old_token_statuses = old.token_statuses;
+        let mut token_statuses = UnorderedMap::new(StorageKey::TokenStatuses);
+        for (token_id, status) in old_token_statuses.iter() {
+            token_statuses.insert(&token_id, &Status::from(status));
+        }
+        old_token_statuses.clear();
+
+        let price_feed_addr = AccountId::from_str("priceoracle.testnet").expect("accountID should be valid");
+
+        Self {
+            tokens: old.tokens,
+            metadata: old.metadata,
+            next_token_id: old.next_token_id,
+            mint_authorizer: old.mint_authorizer,
+            authorized_token_metadata: old.authorized_token_metadata,
+            authorized_statuses,
+            token_statuses,
+            subscription_cost_per_year: 5 * u32::pow(10, SUBSCRIPTION_COST_DECIMALS as u32),
+            authorized_seconds_to_pay: UnorderedMap::new(StorageKey::AuthorizedSecondsToPay),
+            authorized_tiers: UnorderedMap::new(StorageKey::AuthorizedTiers),
+            token_tiers: UnorderedMap::new(StorageKey::TokenTiers),
+            native_usd_price_feed: PriceFeedV0_4_1::new(price_feed_addr),
+        }
+    }
+}
+
+impl From<KycdaoNTNFTV0_4_1> for KycdaoNTNFTV0_4_2 {
+    fn from(old: KycdaoNTNFTV0_4_1) -> Self {
+        Self {
+            tokens: old.tokens,
+            metadata: old.metadata,
+            next_token_id: old.next_token_id,
+            mint_authorizer: old.mint_authorizer,
+            authorized_token_metadata: old.authorized_token_metadata,
+            authorized_statuses: old.authorized_statuses,
+            token_statuses: old.token_statuses,
+            subscription_cost_per_year: old.subscription_cost_per_year,
+            authorized_seconds_to_pay: old.authorized_seconds_to_pay,
+            authorized_tiers: old.authorized_tiers,
+            token_tiers: old.token_tiers,
+            native_usd_price_feed: PriceFeedMocked::from(old.native_usd_price_feed),
+        }
+    }
+}
+
+/// Reads the contract state and migrates it to the current layout.
+/// Versions before v0.5.0 had no storage version, so their layout is detected by
+/// deserializing the state with each known layout. Borsh requires all bytes to be
+/// consumed, so only the matching layout can succeed.
+pub fn migrate_state(chain_id: Option<String>) -> KycdaoNTNFT {
+    let state = env::storage_read(b"STATE").expect("Contract is not initialized");
+
+    if let Ok(current) = KycdaoNTNFT::try_from_slice(&state) {
+        assert!(current.storage_version == VERSION, "Unsupported storage version");
+        assert!(chain_id.as_ref().map_or(true, |chain_id| chain_id == &current.chain_id), "Chain ID cannot be changed");
+        return current;
+    }
+
+    let chain_id = || chain_id.clone().expect("Chain ID is required to migrate from versions before v0.5.0");
+    let contract = if let Ok(old) = KycdaoNTNFTV0_4_2::try_from_slice(&state) {
+        migrate_from_v0_4_2(old, chain_id())
+    } else if let Ok(old) = KycdaoNTNFTV0_4_1::try_from_slice(&state) {
+        migrate_from_v0_4_1(old, chain_id())
+    } else if let Ok(old) = KycdaoNTNFTV0_3_2::try_from_slice(&state) {
+        migrate_from_v0_3_2(old, chain_id())
+    } else {
+        env::panic_str("Unsupported storage version")
+    };
+
+    StorageVersionUpdated { version: &contract.storage_version }.emit();
+    contract
+}
+
+fn migrate_from_v0_3_2(old: KycdaoNTNFTV0_3_2, chain_id: String) -> KycdaoNTNFT {
+    near_sdk::log!("Migrating storage from v0.3.2");
+    migrate_from_v0_4_1(KycdaoNTNFTV0_4_1::from(old), chain_id)
+}
+
+fn migrate_from_v0_4_1(old: KycdaoNTNFTV0_4_1, chain_id: String) -> KycdaoNTNFT {
+    near_sdk::log!("Migrating storage from v0.4.1");
+    migrate_from_v0_4_2(KycdaoNTNFTV0_4_2::from(old), chain_id)
+}
+
+/// Creates all state added in v0.5.0, which was released as one layout.
+/// The owner keeps managing the contract and the prices, and the mint authorizer
+/// gets the roles for minting and revoking tokens. Previous versions did not store the chain ID,
+/// so it has to be passed to `migrate`
+fn migrate_from_v0_4_2(old: KycdaoNTNFTV0_4_2, chain_id: String) -> KycdaoNTNFT {
+    near_sdk::log!("Migrating storage from v0.4.2");
+    KycdaoNTNFT::assert_valid_chain_id(&chain_id);
+    let mut roles = Roles::new(&old.tokens.owner_id);
+    roles.grant(Role::PricingAdmin, &old.tokens.owner_id);
+    roles.grant(Role::Minter, &old.mint_authorizer);
+    roles.grant(Role::Revoker, &old.mint_authorizer);
+
+    KycdaoNTNFT {
+        tokens: old.tokens,
+        metadata: old.metadata,
+        next_token_id: old.next_token_id,
+        roles,
+        authorized_token_metadata: old.authorized_token_metadata,
+        authorized_statuses: old.authorized_statuses,
+        token_statuses: old.token_statuses,
+        subscription_cost_per_year: old.subscription_cost_per_year,
+        authorized_seconds_to_pay: old.authorized_seconds_to_pay,
+        authorized_tiers: old.authorized_tiers,
+        token_tiers: old.token_tiers,
+        native_usd_price_feed: PriceFeed::from_mocked(old.native_usd_price_feed, &chain_id),
+        signer_keys: LookupMap::new(StorageKey::SignerKeys),
+        used_mint_signatures: LookupSet::new(StorageKey::UsedMintSignatures),
+        authorized_deadlines: UnorderedMap::new(StorageKey::AuthorizedDeadlines),
+        storage_version: VERSION.to_string(),
+        tiers: KycdaoNTNFT::initial_tiers(),
+        duration_prices: UnorderedMap::new(StorageKey::DurationPrices),
+        used_renewal_signatures: LookupSet::new(StorageKey::UsedRenewalSignatures),
+        revocation_history: LookupMap::new(StorageKey::RevocationHistory),
+        authorization_deposits: LookupMap::new(StorageKey::AuthorizationDeposits),
+        safe_address: None,
+        balance_reserve: DEFAULT_BALANCE_RESERVE,
+        token_uri_template: TokenUriTemplate::BaseUri,
+        chain_id,
+        // Pending authorizations keep working with their legacy digests, until they are moved
+        // to the current digest format with `migrate_legacy_authorizations`
+        authorization_digests: LookupMap::new(StorageKey::AuthorizationDigests),
+        authorization_nonce: 0,
+        // Tokens of previous versions have the plain index as ID
+        token_id_prefix: String::new(),
+        burned_tokens: 0,
+        token_policy: TokenPolicy::Multiple,
+        pending_token_updates: LookupMap::new(StorageKey::PendingTokenUpdates),
+        paused_features: Vec::new(),
+        // The storage of tokens minted by previous versions was not paid by their owners, so it is not refunded on burn
+        token_storage_deposits: LookupMap::new(StorageKey::TokenStorageDeposits),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::testing_env;
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::json_types::U128;
+    use near_contract_standards::upgrade::Ownable;
+
+    const CHAIN_ID: &str = "near:testnet";
+
+    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
+        let mut builder = VMContextBuilder::new();
+        builder
+            .current_account_id(accounts(0))
+            .signer_account_id(predecessor_account_id.clone())
+            .predecessor_account_id(predecessor_account_id);
+        builder
+    }
+
+    fn sample_metadata() -> NTNFTContractMetadata {
+        NTNFTContractMetadata {
+            spec: NTNFT_METADATA_SPEC.to_string(),
+            name: "KycDAO Identity".to_string(),
+            symbol: "PEOPLE".to_string(),
+            icon: None,
+            base_uri: Some("ipfs://".to_string()),
+            reference: None,
+            reference_hash: None,
+        }
+    }
+
+    fn v0_3_2_state() -> KycdaoNTNFTV0_3_2 {
+        let mut state = KycdaoNTNFTV0_3_2 {
+            tokens: NTNFT::new(
+                StorageKey::NonFungibleToken,
+                accounts(1),
+                Some(StorageKey::TokenMetadata),
+                Some(StorageKey::Enumeration),
+            ),
+            metadata: LazyOption::new(StorageKey::Metadata, Some(&sample_metadata())),
+            next_token_id: 2,
+            mint_authorizer: accounts(2),
+            authorized_token_metadata: LookupMap::new(StorageKey::AuthorizedTokenMetadata),
+            authorized_statuses: UnorderedMap::new(StorageKey::AuthorizedStatusesV0_3_2),
+            token_statuses: UnorderedMap::new(StorageKey::TokenStatusesV0_3_2),
+        };
+        state.authorized_statuses.insert(&vec![1, 2, 3], &StatusV0_3_2 { is_revoked: false, expiry: Some(1700000000) });
+        state.token_statuses.insert(&"0".to_string(), &StatusV0_3_2 { is_revoked: true, expiry: None });
+        state.token_statuses.insert(&"1".to_string(), &StatusV0_3_2 { is_revoked: false, expiry: Some(1700000000) });
+        state
+    }
+
+    fn v0_4_1_state() -> KycdaoNTNFTV0_4_1 {
+        let mut state = KycdaoNTNFTV0_4_1::from(v0_3_2_state());
+        state.subscription_cost_per_year = 7 * u32::pow(10, SUBSCRIPTION_COST_DECIMALS as u32);
+        state.native_usd_price_feed = PriceFeedV0_4_1::new(accounts(3));
+        state.token_tiers.insert(&"1".to_string(), &"KYC_2".to_string());
+        state
+    }
+
+    #[test]
+    fn test_migrate_from_v0_3_2() {
+        testing_env!(get_context(accounts(0)).build());
+        env::state_write(&v0_3_2_state());
+
+        let contract = KycdaoNTNFT::migrate(Some(CHAIN_ID.to_string()));
+        assert_eq!(contract.storage_version(), VERSION);
+        assert_eq!(contract.get_owner(), accounts(1));
+        assert_eq!(contract.get_role_members(Role::Owner), vec![accounts(1)]);
+        assert_eq!(contract.get_role_members(Role::PricingAdmin), vec![accounts(1)]);
+        assert_eq!(contract.get_role_members(Role::Minter), vec![accounts(2)]);
+        assert_eq!(contract.get_role_members(Role::Revoker), vec![accounts(2)]);
+        assert_eq!(contract.next_token_id, 2);
+        assert_eq!(contract.get_subscription_cost_per_year_usd(), 5 * u32::pow(10, SUBSCRIPTION_COST_DECIMALS as u32));
+        assert_eq!(contract.get_price_feed(), AccountId::from_str("priceoracle.testnet").unwrap());
+
+        let revoked = contract.token_statuses.get(&"0".to_string()).unwrap();
+        assert!(!revoked.verified);
+        let verified = contract.token_statuses.get(&"1".to_string()).unwrap();
+        assert!(verified.verified);
+        assert_eq!(verified.expiry, Some(1700000000));
+        assert!(contract.token_tiers.get(&"1".to_string()).is_none());
+        assert!(contract.authorized_statuses.get(&vec![1, 2, 3]).unwrap().verified);
+
+        let old_statuses: UnorderedMap<TokenId, StatusV0_3_2> = UnorderedMap::new(StorageKey::TokenStatusesV0_3_2);
+        assert!(old_statuses.is_empty());
+        let old_authorized_statuses: UnorderedMap<Vec<u8>, StatusV0_3_2> = UnorderedMap::new(StorageKey::AuthorizedStatusesV0_3_2);
+        assert!(old_authorized_statuses.is_empty());
+        assert_eq!(get_logs(), vec![
+            "Migrating storage from v0.3.2",
+            "Migrating storage from v0.4.1",
+            "Migrating storage from v0.4.2",
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"storage_version_updated","data":[{"version":"0.5.0"}]}"#,
+        ]);
+    }
+
+    #[test]
+    fn test_migrate_from_v0_4_1() {
+        testing_env!(get_context(accounts(0)).build());
+        env::state_write(&v0_4_1_state());
+
+        let contract = KycdaoNTNFT::migrate(Some(CHAIN_ID.to_string()));
+        assert_eq!(contract.storage_version(), VERSION);
+        assert_eq!(contract.get_subscription_cost_per_year_usd(), 7 * u32::pow(10, SUBSCRIPTION_COST_DECIMALS as u32));
+        assert_eq!(contract.get_price_feed(), accounts(3));
+        assert_eq!(contract.token_tiers.get(&"1".to_string()), Some("KYC_2".to_string()));
+        assert_eq!(contract.token_statuses.get(&"1".to_string()).unwrap().expiry, Some(1700000000));
+    }
+
+    #[test]
+    fn test_migrate_from_v0_4_2() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut state = KycdaoNTNFTV0_4_2::from(v0_4_1_state());
+        state.native_usd_price_feed = PriceFeedMocked::new(accounts(4));
+        env::state_write(&state);
+
+        let contract = KycdaoNTNFT::migrate(Some(CHAIN_ID.to_string()));
+        assert_eq!(contract.storage_version(), VERSION);
+        assert_eq!(contract.get_price_feed(), accounts(4));
+        assert_eq!(contract.get_price_feed_asset(), "wrap.testnet");
+        assert!(contract.get_signer_key(accounts(2)).is_none());
+        assert_eq!(contract.get_chain_id(), CHAIN_ID);
+        assert_eq!(contract.token_tiers.get(&"1".to_string()), Some("KYC_2".to_string()));
+        assert_eq!(get_logs(), vec![
+            "Migrating storage from v0.4.2",
+            r#"EVENT_JSON:{"standard":"kycdao","version":"1.0.0","event":"storage_version_updated","data":[{"version":"0.5.0"}]}"#,
+        ]);
+    }
+
+    #[test]
+    fn test_migrate_from_v0_4_2_on_mainnet() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut state = KycdaoNTNFTV0_4_2::from(v0_4_1_state());
+        state.native_usd_price_feed = PriceFeedMocked::new("priceoracle.near".parse().unwrap());
+        env::state_write(&state);
+
+        let contract = KycdaoNTNFT::migrate(Some("near:mainnet".to_string()));
+        assert_eq!(contract.get_chain_id(), "near:mainnet");
+        assert_eq!(contract.get_price_feed(), AccountId::from_str("priceoracle.near").unwrap());
+        assert_eq!(contract.get_price_feed_asset(), "wrap.near");
+    }
+
+    #[test]
+    fn test_migrate_current_version() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = KycdaoNTNFT::new(sample_metadata(), Some("kyc-".to_string()), CHAIN_ID.to_string());
+        contract.set_price_feed(accounts(4), "wrap.near".to_string());
+        env::state_write(&contract);
+
+        // Drop the logs of the setup
+        testing_env!(get_context(accounts(0)).build());
+        let contract = KycdaoNTNFT::migrate(None);
+        assert_eq!(contract.storage_version(), VERSION);
+        assert_eq!(contract.token_id_for_index(U128(7)), "kyc-7");
+        assert_eq!(contract.get_price_feed(), accounts(4));
+        assert_eq!(contract.get_price_feed_asset(), "wrap.near");
+        assert!(get_logs().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unsupported storage version")]
+    fn test_migrate_unsupported_version() {
+        testing_env!(get_context(accounts(0)).build());
+        let mut contract = KycdaoNTNFT::new(sample_metadata(), None, CHAIN_ID.to_string());
+        contract.storage_version = "0.4.3".to_string();
+        env::state_write(&contract);
+
+        KycdaoNTNFT::migrate(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Chain ID is required to migrate from versions before v0.5.0")]
+    fn test_migrate_without_chain_id() {
+        testing_env!(get_context(accounts(0)).build());
+        env::state_write(&v0_3_2_state());
+
+        KycdaoNTNFT::migrate(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid chain ID")]
+    fn test_migrate_with_invalid_chain_id() {
+        testing_env!(get_context(accounts(0)).build());
+        env::state_write(&v0_3_2_state());
+
+        KycdaoNTNFT::migrate(Some("testnet".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Chain ID cannot be changed")]
+    fn test_migrate_changing_chain_id() {
+        testing_env!(get_context(accounts(0)).build());
+        let contract = KycdaoNTNFT::new(sample_metadata(), None, CHAIN_ID.to_string());
+        env::state_write(&contract);
+
+        KycdaoNTNFT::migrate(Some("near:mainnet".to_string()));
+    }
+}
@@ -1,11 +1,69 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::AccountId;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{env, ext_contract, AccountId, Gas, Promise};
+use serde::{Serialize, Deserialize};
 
+use crate::YOCTONEAR_TO_NATIVE_DECIMALS;
+
+/// Gas reserved for the `get_price_data` call on the price oracle
+pub const GAS_FOR_GET_PRICE_DATA: Gas = Gas(10_000_000_000_000);
+/// Maximum number of decimals of the USD price of one NEAR. The subscription cost (u32) is multiplied by
+/// 10^(16 + decimals) when converting it to yoctoNEAR, which has to fit in a u128
+pub const MAX_PRICE_DECIMALS: u8 = 12;
+/// Default maximum age of an oracle price before it is considered stale
+pub const DEFAULT_PRICE_MAX_AGE_SECS: u32 = 5 * 60;
+/// Chain ID of NEAR mainnet, every other network uses the testnet price feed by default
+pub const MAINNET_CHAIN_ID: &str = "near:mainnet";
+/// Price oracle and asset ID of wrapped NEAR on mainnet
+pub const MAINNET_PRICE_ORACLE: &str = "priceoracle.near";
+pub const MAINNET_PRICE_FEED_ASSET: &str = "wrap.near";
+/// Price oracle and asset ID of wrapped NEAR on testnet
+pub const TESTNET_PRICE_ORACLE: &str = "priceoracle.testnet";
+pub const TESTNET_PRICE_FEED_ASSET: &str = "wrap.testnet";
+
+pub type AssetId = String;
+
+/// Price of an asset as reported by the `priceoracle` contract
+/// `multiplier / 10^decimals` is the USD price of the smallest unit of the asset
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Price {
+    pub multiplier: U128,
+    pub decimals: u8,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AssetOptionalPrice {
+    pub asset_id: AssetId,
+    pub price: Option<Price>,
+}
+
+/// Response of `get_price_data` on the `priceoracle` contract
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PriceData {
+    /// Timestamp of the price data (nanoseconds)
+    pub timestamp: U64,
+    pub recency_duration_sec: u32,
+    pub prices: Vec<AssetOptionalPrice>,
+}
+
+#[ext_contract(ext_price_oracle)]
+pub trait PriceOracle {
+    fn get_price_data(&self, asset_ids: Option<Vec<AssetId>>) -> PriceData;
+}
+
+/// Price feed stored by v0.4.1, which only had the address
 #[derive(BorshDeserialize, BorshSerialize)]
-pub struct PriceFeed {
+pub struct PriceFeedV0_4_1 {
     address: AccountId,
 }
 
+impl PriceFeedV0_4_1 {
+    pub fn new(address: AccountId) -> Self {
+        PriceFeedV0_4_1 { address }
+    }
+}
+
+/// Price feed stored by v0.4.2, with the price set manually by the owner
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct PriceFeedMocked {
     address: AccountId,
@@ -20,15 +78,104 @@ impl PriceFeedMocked {
         }
     }
 
-    pub fn set_latest_price(&mut self, price: u32, decimals: u8) {
-        self.latest_price = (price, decimals);
+    pub fn price_feed_address(&self) -> AccountId {
+        self.address.clone()
+    }
+}
+
+impl From<PriceFeedV0_4_1> for PriceFeedMocked {
+    fn from(old: PriceFeedV0_4_1) -> Self {
+        PriceFeedMocked::new(old.address)
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct PriceFeed {
+    address: AccountId,
+    asset_id: AssetId,
+    /// Prices older than this are rejected
+    max_age_secs: u32,
+    /// The last valid price received from the oracle
+    latest_price: Option<(u32, u8)>,
+}
+
+impl PriceFeed {
+    pub fn new(address: AccountId, asset_id: AssetId) -> Self {
+        PriceFeed {
+            address,
+            asset_id,
+            max_age_secs: DEFAULT_PRICE_MAX_AGE_SECS,
+            latest_price: None,
+        }
+    }
+
+    /// The price feed of wrapped NEAR on the official oracle of the network
+    pub fn default_for_chain(chain_id: &str) -> Self {
+        let address = if chain_id == MAINNET_CHAIN_ID { MAINNET_PRICE_ORACLE } else { TESTNET_PRICE_ORACLE };
+        PriceFeed::new(address.parse().expect("accountID should be valid"), Self::default_asset_id(chain_id))
+    }
+
+    /// Price feeds stored before v0.5.0 did not have the asset ID, so it is derived from the network
+    pub fn from_mocked(old: PriceFeedMocked, chain_id: &str) -> Self {
+        PriceFeed::new(old.price_feed_address(), Self::default_asset_id(chain_id))
+    }
+
+    fn default_asset_id(chain_id: &str) -> AssetId {
+        if chain_id == MAINNET_CHAIN_ID { MAINNET_PRICE_FEED_ASSET } else { TESTNET_PRICE_FEED_ASSET }.to_string()
+    }
+
+    /// Requests the current price data for the asset from the oracle
+    pub fn get_price_data(&self) -> Promise {
+        ext_price_oracle::ext(self.address.clone())
+            .with_static_gas(GAS_FOR_GET_PRICE_DATA)
+            .get_price_data(Some(vec![self.asset_id.clone()]))
+    }
+
+    /// Validates the oracle response and converts it to a USD price of one NEAR,
+    /// in the format of (price, decimals)
+    pub fn parse_price_data(&self, price_data: &PriceData) -> Result<(u32, u8), String> {
+        let max_age = self.max_age_secs as u64 * u64::pow(10, 9);
+        if price_data.timestamp.0 + max_age < env::block_timestamp() {
+            return Err("Price is stale".to_string());
+        }
+
+        let price = price_data.prices.iter()
+            .find(|p| p.asset_id == self.asset_id)
+            .and_then(|p| p.price.clone())
+            .ok_or_else(|| format!("Missing price for {}", self.asset_id))?;
+
+        if price.decimals < YOCTONEAR_TO_NATIVE_DECIMALS || price.decimals > YOCTONEAR_TO_NATIVE_DECIMALS + MAX_PRICE_DECIMALS {
+            return Err("Unsupported price decimals".to_string());
+        }
+        let multiplier = u32::try_from(price.multiplier.0).map_err(|_| "Price out of range".to_string())?;
+        if multiplier == 0 {
+            return Err("Invalid price".to_string());
+        }
+
+        Ok((multiplier, price.decimals - YOCTONEAR_TO_NATIVE_DECIMALS))
+    }
+
+    pub fn set_latest_price(&mut self, price: (u32, u8)) {
+        self.latest_price = Some(price);
     }
 
-    pub fn latest_price(&self) -> (u32, u8) {
+    pub fn latest_price(&self) -> Option<(u32, u8)> {
         self.latest_price
     }
 
+    pub fn set_max_age_secs(&mut self, max_age_secs: u32) {
+        self.max_age_secs = max_age_secs;
+    }
+
+    pub fn max_age_secs(&self) -> u32 {
+        self.max_age_secs
+    }
+
     pub fn price_feed_address(&self) -> AccountId {
         self.address.clone()
     }
-}
\ No newline at end of file
+
+    pub fn asset_id(&self) -> AssetId {
+        self.asset_id.clone()
+    }
+}
@@ -0,0 +1,91 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, UnorderedSet};
+use near_sdk::AccountId;
+use serde::{Serialize, Deserialize};
+use std::fmt;
+
+use crate::StorageKey;
+
+/// Roles which can be granted to accounts, based on the AccessControl roles of the EVM contract
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Can grant and revoke roles, and manage the contract
+    Owner,
+    /// Can authorize mints and update the expiry of tokens
+    Minter,
+    /// Can revoke and reinstate tokens
+    Revoker,
+    /// Can set the subscription cost and the price feed
+    PricingAdmin,
+    /// Can pause, but not unpause, parts of the contract in an emergency
+    Guardian,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Role::Owner => "owner",
+            Role::Minter => "minter",
+            Role::Revoker => "revoker",
+            Role::PricingAdmin => "pricing_admin",
+            Role::Guardian => "guardian",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Roles {
+    members: LookupMap<Role, UnorderedSet<AccountId>>,
+}
+
+impl Roles {
+    /// Creates the registry with the owner role granted to `owner`
+    pub fn new(owner: &AccountId) -> Self {
+        let mut roles = Roles {
+            members: LookupMap::new(StorageKey::Roles),
+        };
+        roles.grant(Role::Owner, owner);
+        roles
+    }
+
+    pub fn has_role(&self, role: Role, account_id: &AccountId) -> bool {
+        self.members.get(&role)
+            .map(|members| members.contains(account_id))
+            .unwrap_or(false)
+    }
+
+    pub fn members(&self, role: Role) -> Vec<AccountId> {
+        self.members.get(&role)
+            .map(|members| members.to_vec())
+            .unwrap_or_default()
+    }
+
+    pub fn count(&self, role: Role) -> u64 {
+        self.members.get(&role)
+            .map(|members| members.len())
+            .unwrap_or(0)
+    }
+
+    /// @return false if the account already had the role
+    pub fn grant(&mut self, role: Role, account_id: &AccountId) -> bool {
+        let mut members = self.members.get(&role)
+            .unwrap_or_else(|| UnorderedSet::new(StorageKey::RoleMembers { role }));
+        let granted = members.insert(account_id);
+        self.members.insert(&role, &members);
+        granted
+    }
+
+    /// @return false if the account did not have the role
+    pub fn revoke(&mut self, role: Role, account_id: &AccountId) -> bool {
+        match self.members.get(&role) {
+            Some(mut members) => {
+                let revoked = members.remove(account_id);
+                self.members.insert(&role, &members);
+                revoked
+            },
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::UnorderedMap;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::*;
+
+use serde::{Serialize, Deserialize};
+
+type AssetId = String;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug)]
+pub struct Price {
+    pub multiplier: U128,
+    pub decimals: u8,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AssetOptionalPrice {
+    pub asset_id: AssetId,
+    pub price: Option<Price>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PriceData {
+    pub timestamp: U64,
+    pub recency_duration_sec: u32,
+    pub prices: Vec<AssetOptionalPrice>,
+}
+
+/// Local stand-in for the `priceoracle` contract, with prices set by the owner
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct MockPriceOracle {
+    owner_id: AccountId,
+    prices: UnorderedMap<AssetId, Price>,
+    /// Subtracted from the block timestamp of the returned price data, to simulate stale prices
+    price_age_secs: u64,
+}
+
+#[near_bindgen]
+impl MockPriceOracle {
+    #[init]
+    pub fn new() -> Self {
+        assert!(!env::state_exists(), "Already initialized");
+        Self {
+            owner_id: env::predecessor_account_id(),
+            prices: UnorderedMap::new(b"p".to_vec()),
+            price_age_secs: 0,
+        }
+    }
+
+    pub fn set_price(&mut self, asset_id: AssetId, price: Price) {
+        self.assert_owner();
+        self.prices.insert(&asset_id, &price);
+    }
+
+    pub fn remove_price(&mut self, asset_id: AssetId) {
+        self.assert_owner();
+        self.prices.remove(&asset_id);
+    }
+
+    pub fn set_price_age(&mut self, seconds: u64) {
+        self.assert_owner();
+        self.price_age_secs = seconds;
+    }
+
+    pub fn get_price_data(&self, asset_ids: Option<Vec<AssetId>>) -> PriceData {
+        let asset_ids = asset_ids.unwrap_or_else(|| self.prices.keys().collect());
+        PriceData {
+            timestamp: U64(env::block_timestamp().saturating_sub(self.price_age_secs * u64::pow(10, 9))),
+            recency_duration_sec: 90,
+            prices: asset_ids.into_iter()
+                .map(|asset_id| AssetOptionalPrice {
+                    price: self.prices.get(&asset_id),
+                    asset_id,
+                })
+                .collect(),
+        }
+    }
+
+    fn assert_owner(&self) {
+        assert_eq!(env::predecessor_account_id(), self.owner_id, "Predecessor must be owner");
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::testing_env;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+
+    #[test]
+    fn test_price_data() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(0)).block_timestamp(1664226405000000000).build());
+        let mut oracle = MockPriceOracle::new();
+        oracle.set_price("wrap.testnet".to_string(), Price { multiplier: U128(17370), decimals: 28 });
+        oracle.set_price_age(60);
+
+        let data = oracle.get_price_data(Some(vec!["wrap.testnet".to_string(), "usdt.testnet".to_string()]));
+        assert_eq!(data.timestamp.0, 1664226345000000000);
+        assert_eq!(data.prices[0].price.as_ref().unwrap().multiplier.0, 17370);
+        assert!(data.prices[1].price.is_none());
+    }
+}